@@ -0,0 +1,308 @@
+//! Access logging of fetch handlers.
+
+use log::kv::Value;
+use log::{Level, Record};
+use worker::{Request, Response, ResponseBody, Result};
+
+use crate::format;
+use crate::platform;
+
+use std::fmt::Write;
+use std::future::Future;
+
+/// Target of access records, e.g. `worker_logger::access=off` in a filter string disables them
+pub(crate) const TARGET: &str = "worker_logger::access";
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Layout of access records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AccessFormat {
+    /// A short message such as `GET /path 200`, with the details attached as key-values:
+    /// `method`, `url`, `status`, `size`, `latency_ms`, `colo` and `country`
+    #[default]
+    Fields,
+    /// The message is a line in the Common Log Format, without key-values
+    Common,
+    /// The message is a line in the Combined Log Format, which adds the referer and user agent to
+    /// the Common Log Format, without key-values
+    Combined,
+}
+
+/// Logs one record per request once its handler completes
+///
+/// Records use the `worker_logger::access` target, so they go through the filter of the installed
+/// logger like any other. Responses with a 5xx status are logged as `Error`, 4xx as `Warn` and
+/// everything else as `Info`. A handler returning an error is logged with a 500 status, and the
+/// error is passed on unchanged.
+///
+/// ```rust,ignore
+/// use worker::*;
+/// use worker_logger::AccessLog;
+///
+/// #[event(fetch)]
+/// async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+///     AccessLog::new().serve(req, |req| handle(req, env)).await
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AccessLog {
+    format: AccessFormat,
+}
+
+impl AccessLog {
+    /// Log requests with short messages and key-values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the layout of access records
+    pub fn format(mut self, format: AccessFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Run a handler on a request, logging the request once the handler completes
+    pub async fn serve<F, Fut>(&self, req: Request, handler: F) -> Result<Response>
+    where
+        F: FnOnce(Request) -> Fut,
+        Fut: Future<Output = Result<Response>>,
+    {
+        let mut access = Access::from_request(&req);
+        let result = handler(req).await;
+        access.finish(&result);
+        access.log(self.format);
+        result
+    }
+}
+
+/// What is known about a request and its response
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Access {
+    pub(crate) method: String,
+    pub(crate) url: String,
+    /// Path and query, as in the request line
+    pub(crate) path: String,
+    pub(crate) protocol: Option<String>,
+    pub(crate) remote: Option<String>,
+    pub(crate) referer: Option<String>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) colo: Option<String>,
+    pub(crate) country: Option<String>,
+    pub(crate) started: u64,
+    pub(crate) status: u16,
+    /// Missing for streamed bodies without a `content-length`
+    pub(crate) size: Option<u64>,
+    pub(crate) latency: u64,
+    pub(crate) error: Option<String>,
+}
+
+impl Access {
+    /// Read the request details, before the request is handed to the handler
+    pub(crate) fn from_request(req: &Request) -> Self {
+        let header = |name| req.headers().get(name).ok().flatten();
+        let (url, path) = match req.url() {
+            Ok(url) => {
+                let mut path = url.path().to_string();
+                if let Some(query) = url.query() {
+                    path.push('?');
+                    path.push_str(query);
+                }
+                (url.to_string(), path)
+            }
+            Err(_) => (req.path(), req.path()),
+        };
+        let cf = req.cf();
+        Access {
+            method: req.method().to_string(),
+            url,
+            path,
+            protocol: cf.map(|cf| cf.http_protocol()),
+            remote: header("cf-connecting-ip"),
+            referer: header("referer"),
+            user_agent: header("user-agent"),
+            colo: cf.map(|cf| cf.colo()),
+            country: cf.and_then(|cf| cf.country()),
+            started: platform::now_millis(),
+            ..Self::default()
+        }
+    }
+
+    /// Record the outcome of the handler
+    pub(crate) fn finish(&mut self, result: &Result<Response>) {
+        self.latency = platform::now_millis().saturating_sub(self.started);
+        match result {
+            Ok(response) => {
+                self.status = response.status_code();
+                self.size = match response.body() {
+                    ResponseBody::Empty => Some(0),
+                    ResponseBody::Body(body) => Some(body.len() as u64),
+                    ResponseBody::Stream(_) => response
+                        .headers()
+                        .get("content-length")
+                        .ok()
+                        .flatten()
+                        .and_then(|length| length.parse().ok()),
+                };
+            }
+            Err(error) => {
+                self.status = 500;
+                self.size = None;
+                self.error = Some(error.to_string());
+            }
+        }
+    }
+
+    /// The level of the record, derived from the status class
+    pub(crate) fn level(&self) -> Level {
+        match self.status {
+            500.. => Level::Error,
+            400..=499 => Level::Warn,
+            _ => Level::Info,
+        }
+    }
+
+    /// Render the message of the record
+    pub(crate) fn message(&self, format: AccessFormat) -> String {
+        match format {
+            AccessFormat::Fields => format!("{} {} {}", self.method, self.path, self.status),
+            AccessFormat::Common | AccessFormat::Combined => {
+                let (year, month, day, hour, minute, second) = format::civil(self.started);
+                let mut out = format!(
+                    "{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} {}\" {} ",
+                    self.remote.as_deref().unwrap_or("-"),
+                    day,
+                    MONTHS[month as usize - 1],
+                    year,
+                    hour,
+                    minute,
+                    second,
+                    self.method,
+                    self.path,
+                    self.protocol.as_deref().unwrap_or("HTTP/1.1"),
+                    self.status,
+                );
+                let _ = match self.size {
+                    Some(size) => write!(out, "{}", size),
+                    None => write!(out, "-"),
+                };
+                if format == AccessFormat::Combined {
+                    let _ = write!(
+                        out,
+                        " {:?} {:?}",
+                        self.referer.as_deref().unwrap_or("-"),
+                        self.user_agent.as_deref().unwrap_or("-")
+                    );
+                }
+                out
+            }
+        }
+    }
+
+    /// Send the record to the installed logger
+    pub(crate) fn log(&self, format: AccessFormat) {
+        let level = self.level();
+        if level > log::max_level() {
+            return;
+        }
+        let message = self.message(format);
+        let mut fields: Vec<(&str, Value)> = Vec::new();
+        if format == AccessFormat::Fields {
+            fields.push(("method", Value::from(self.method.as_str())));
+            fields.push(("url", Value::from(self.url.as_str())));
+            fields.push(("status", Value::from(self.status)));
+            if let Some(size) = self.size {
+                fields.push(("size", Value::from(size)));
+            }
+            fields.push(("latency_ms", Value::from(self.latency)));
+            let optional = [
+                ("colo", &self.colo),
+                ("country", &self.country),
+                ("error", &self.error),
+            ];
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    fields.push((key, Value::from(value.as_str())));
+                }
+            }
+        }
+        log::logger().log(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(level)
+                .target(TARGET)
+                .key_values(&fields)
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn access(status: u16) -> Access {
+        Access {
+            method: "GET".to_string(),
+            url: "https://example.com/items?page=2".to_string(),
+            path: "/items?page=2".to_string(),
+            remote: Some("203.0.113.7".to_string()),
+            user_agent: Some("curl/8.0".to_string()),
+            colo: Some("AMS".to_string()),
+            // 2024-02-29T13:05:09Z
+            started: 1_709_211_909_000,
+            status,
+            size: Some(512),
+            latency: 12,
+            ..Access::default()
+        }
+    }
+
+    #[test]
+    fn level_follows_status_class() {
+        assert_eq!(access(200).level(), Level::Info);
+        assert_eq!(access(304).level(), Level::Info);
+        assert_eq!(access(404).level(), Level::Warn);
+        assert_eq!(access(503).level(), Level::Error);
+    }
+
+    #[test]
+    fn log_formats() {
+        let access = access(404);
+        assert_eq!(
+            access.message(AccessFormat::Fields),
+            "GET /items?page=2 404"
+        );
+        assert_eq!(
+            access.message(AccessFormat::Common),
+            "203.0.113.7 - - [29/Feb/2024:13:05:09 +0000] \"GET /items?page=2 HTTP/1.1\" 404 512"
+        );
+        assert_eq!(
+            access.message(AccessFormat::Combined),
+            "203.0.113.7 - - [29/Feb/2024:13:05:09 +0000] \"GET /items?page=2 HTTP/1.1\" 404 512 \
+             \"-\" \"curl/8.0\""
+        );
+    }
+
+    #[test]
+    fn records_carry_request_details() {
+        let logs = testing::capture();
+        access(404).log(AccessFormat::Fields);
+        logs.assert_logged_with(
+            Level::Warn,
+            "GET /items?page=2 404",
+            &[
+                ("status", 404.into()),
+                ("size", 512.into()),
+                ("colo", "AMS".into()),
+                ("url", "https://example.com/items?page=2".into()),
+            ],
+        );
+        assert_eq!(logs.entries()[0].target, TARGET);
+        assert_eq!(logs.entries()[0].field("country"), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! Builder for configuring a [`Logger`] before installing it.
+
+use log::Level;
+
+use crate::console::{ConsoleMethod, ConsoleSink};
+use crate::filter;
+use crate::format::{Options, OutputFormat, TimestampFormat};
+use crate::platform;
+use crate::{Error, HttpSink, Logger, Redaction, RingBuffer, Sink, Style, Template};
+
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Builder for a [`Logger`]
+///
+/// Configuring the builder has no global side effects, the maximum log level and color settings
+/// are only applied once the logger is installed.
+///
+/// ```rust
+/// use log::Level;
+/// use worker_logger::{ConsoleMethod, LoggerBuilder, OutputFormat};
+///
+/// LoggerBuilder::new()
+///     .filter("debug")
+///     .timestamp(false)
+///     .format(OutputFormat::Json)
+///     .console_method(Level::Info, ConsoleMethod::Info)
+///     .install()
+///     .unwrap();
+/// ```
+pub struct LoggerBuilder {
+    filter: String,
+    options: Options,
+    template: Template,
+    format: OutputFormat,
+    console: Option<ConsoleSink>,
+    sinks: Vec<Box<dyn Sink>>,
+    http_sink: Option<Arc<HttpSink>>,
+    ring_buffer: Option<RingBuffer>,
+    redaction: Option<Redaction>,
+}
+
+impl fmt::Debug for LoggerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoggerBuilder")
+            .field("filter", &self.filter)
+            .field("options", &self.options)
+            .field("template", &self.template)
+            .field("format", &self.format)
+            .field("console", &self.console)
+            .field("sinks", &self.sinks.len())
+            .field("http_sink", &self.http_sink)
+            .field("ring_buffer", &self.ring_buffer)
+            .field("redaction", &self.redaction)
+            .finish()
+    }
+}
+
+impl Default for LoggerBuilder {
+    fn default() -> Self {
+        LoggerBuilder {
+            filter: Level::Info.as_str().to_string(),
+            options: Options::default(),
+            template: Template::default(),
+            format: OutputFormat::default(),
+            console: Some(ConsoleSink::default()),
+            sinks: Vec::new(),
+            http_sink: None,
+            ring_buffer: None,
+            redaction: None,
+        }
+    }
+}
+
+impl LoggerBuilder {
+    /// Create a builder with the default configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the filter string, `info` by default
+    pub fn filter<S: AsRef<str>>(mut self, filter: S) -> Self {
+        self.filter = filter.as_ref().to_string();
+        self
+    }
+
+    /// Include a timestamp in every record, enabled by default. Enabling timestamps after they
+    /// were disabled restores the default format.
+    pub fn timestamp(mut self, enabled: bool) -> Self {
+        self.options.time = match (enabled, self.options.time) {
+            (false, _) => TimestampFormat::None,
+            (true, TimestampFormat::None) => TimestampFormat::default(),
+            (true, format) => format,
+        };
+        self
+    }
+
+    /// Set how timestamps are written in text output, ISO 8601 by default
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.options.time = format;
+        self
+    }
+
+    /// Include the file and line of the record, enabled by default
+    pub fn source_location(mut self, enabled: bool) -> Self {
+        self.options.location = enabled;
+        self
+    }
+
+    /// Color the text output with ANSI escape codes, enabled by default
+    #[cfg(feature = "color")]
+    pub fn color(mut self, enabled: bool) -> Self {
+        self.options.style = if enabled { Style::Ansi } else { Style::Plain };
+        self
+    }
+
+    /// Set how the text output is styled, ANSI escape codes with the `color` feature and plain
+    /// text otherwise
+    pub fn style(mut self, style: Style) -> Self {
+        self.options.style = style;
+        self
+    }
+
+    /// Set the layout of text output, see [`Template`] for the syntax
+    pub fn template(mut self, template: Template) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Set the output format
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the console method records of the given level are written with, e.g.
+    /// [`ConsoleMethod::Trace`] for `Trace` to capture a JavaScript stack with every record
+    pub fn console_method(mut self, level: Level, method: ConsoleMethod) -> Self {
+        self.console = self
+            .console
+            .map(|console| console.with_method(level, method));
+        self
+    }
+
+    /// Write records to the JavaScript console, enabled by default
+    pub fn console(mut self, enabled: bool) -> Self {
+        self.console = match (enabled, self.console) {
+            (true, console) => Some(console.unwrap_or_default()),
+            (false, _) => None,
+        };
+        self
+    }
+
+    /// Also write records to the given sink
+    pub fn sink<S: Sink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Also buffer every record for shipping to an HTTP collector, see [`crate::flush`]
+    pub fn http_sink(mut self, sink: HttpSink) -> Self {
+        let sink = Arc::new(sink);
+        self.sinks.push(Box::new(sink.clone()));
+        self.http_sink = Some(sink);
+        self
+    }
+
+    /// Retain records below the filter level per request and only write them out if the request
+    /// fails, see [`RingBuffer`]
+    pub fn ring_buffer(mut self, ring_buffer: RingBuffer) -> Self {
+        self.ring_buffer = Some(ring_buffer);
+        self
+    }
+
+    /// Escape line breaks, ANSI escape sequences, bidirectional overrides and other control
+    /// characters in the messages, key-values and span labels of text output, so that logged input
+    /// can't forge log lines or styling. Disabled by default. JSON output is always escaped.
+    pub fn escape_control(mut self, enabled: bool) -> Self {
+        self.options.escape = enabled;
+        self
+    }
+
+    /// Scrub secrets and personal data from every record before it is written out or retained,
+    /// see [`Redaction`]
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = Some(redaction);
+        self
+    }
+
+    /// Build the logger without installing it. Problems in the filter string are only written out
+    /// once the logger is installed, see [`parse_filter`](crate::parse_filter) to check for them.
+    pub fn build(mut self) -> Logger {
+        self.options.style = self.options.style.resolve();
+        // Timestamps relative to the start of the isolate count from here
+        self.options.built = platform::now_millis();
+        let objects = self.format == OutputFormat::Object;
+        Logger {
+            filter: RwLock::new(filter::parse_quietly(&self.filter)),
+            directives: RwLock::new(self.filter.clone()),
+            initial_directives: self.filter,
+            format: self.format,
+            options: self.options,
+            template: self.template,
+            console: self.console.map(|console| console.with_objects(objects)),
+            sinks: self.sinks,
+            http_sink: self.http_sink,
+            ring_buffer: self.ring_buffer,
+            redaction: self.redaction,
+        }
+    }
+
+    /// Build the logger and install it as the main logger
+    pub fn install(self) -> Result<(), Error> {
+        self.build().try_set_logger()
+    }
+}
@@ -0,0 +1,165 @@
+//! Enrichment of the request context with Cloudflare request metadata.
+
+use serde_json::Value;
+use worker::js_sys::Reflect;
+use worker::wasm_bindgen::JsValue;
+use worker::Request;
+
+use crate::context::RequestContext;
+
+/// A property of the `cf` object of an incoming request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CfField {
+    /// Airport code of the data center that processed the request, e.g. `AMS`
+    Colo,
+    /// Two-letter country code of the client, e.g. `NL`
+    Country,
+    /// Autonomous System Number of the client, e.g. `13335`
+    Asn,
+    /// Organization owning the ASN of the client
+    AsOrganization,
+    /// City of the client
+    City,
+    /// Region of the client
+    Region,
+    /// Continent of the client, e.g. `EU`
+    Continent,
+    /// Time zone of the client, e.g. `Europe/Amsterdam`
+    Timezone,
+    /// HTTP protocol of the request, e.g. `HTTP/2`
+    HttpProtocol,
+    /// TLS version of the connection, e.g. `TLSv1.3`
+    TlsVersion,
+    /// TLS cipher of the connection
+    TlsCipher,
+    /// Bot management score from 1 to 99, only present on plans with Bot Management
+    BotScore,
+}
+
+impl CfField {
+    /// The key the property is attached with unless renamed, e.g. `tls_version`
+    pub fn default_name(self) -> &'static str {
+        match self {
+            CfField::Colo => "colo",
+            CfField::Country => "country",
+            CfField::Asn => "asn",
+            CfField::AsOrganization => "as_organization",
+            CfField::City => "city",
+            CfField::Region => "region",
+            CfField::Continent => "continent",
+            CfField::Timezone => "timezone",
+            CfField::HttpProtocol => "http_protocol",
+            CfField::TlsVersion => "tls_version",
+            CfField::TlsCipher => "tls_cipher",
+            CfField::BotScore => "bot_score",
+        }
+    }
+
+    /// Read the property from a request, missing outside of the Cloudflare edge
+    fn read(self, req: &Request) -> Option<Value> {
+        if self == CfField::BotScore {
+            // Not exposed by `worker::Cf`
+            let cf = Reflect::get(req.inner(), &JsValue::from_str("cf")).ok()?;
+            let bot = Reflect::get(&cf, &JsValue::from_str("botManagement")).ok()?;
+            let score = Reflect::get(&bot, &JsValue::from_str("score")).ok()?;
+            return score.as_f64().map(|score| Value::from(score as u64));
+        }
+        let cf = req.cf()?;
+        let value = match self {
+            CfField::Colo => cf.colo().into(),
+            CfField::Country => cf.country()?.into(),
+            CfField::Asn => cf.asn().into(),
+            CfField::AsOrganization => cf.as_organization().into(),
+            CfField::City => cf.city()?.into(),
+            CfField::Region => cf.region()?.into(),
+            CfField::Continent => cf.continent()?.into(),
+            CfField::Timezone => cf.timezone_name().into(),
+            CfField::HttpProtocol => cf.http_protocol().into(),
+            CfField::TlsVersion => cf.tls_version().into(),
+            CfField::TlsCipher => cf.tls_cipher().into(),
+            CfField::BotScore => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Attaches selected `cf` properties of a request to its [`RequestContext`], and so to every
+/// record logged in the request scope
+///
+/// ```rust,ignore
+/// use worker::*;
+/// use worker_logger::{CfEnrichment, CfField, RequestContext};
+///
+/// #[event(fetch)]
+/// async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+///     let context = CfEnrichment::new()
+///         .field(CfField::Colo)
+///         .field(CfField::Country)
+///         .field_as(CfField::BotScore, "bot")
+///         .apply(&req, RequestContext::from_request(&req));
+///     context.scope(handle(req, env)).await
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CfEnrichment {
+    fields: Vec<(CfField, String)>,
+}
+
+impl CfEnrichment {
+    /// Attach no property
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a property under its [default name](CfField::default_name)
+    pub fn field(self, field: CfField) -> Self {
+        self.field_as(field, field.default_name())
+    }
+
+    /// Attach a property under the given name, replacing the name it was previously selected with
+    pub fn field_as<S: Into<String>>(mut self, field: CfField, name: S) -> Self {
+        let name = name.into();
+        match self.fields.iter_mut().find(|(f, _)| *f == field) {
+            Some((_, n)) => *n = name,
+            None => self.fields.push((field, name)),
+        }
+        self
+    }
+
+    /// The selected properties and their names, in the order they were selected
+    pub fn fields(&self) -> &[(CfField, String)] {
+        &self.fields
+    }
+
+    /// Add the selected properties of a request to a context, properties the request lacks are
+    /// skipped
+    pub fn apply(&self, req: &Request, mut context: RequestContext) -> RequestContext {
+        for (field, name) in &self.fields {
+            if let Some(value) = field.read(req) {
+                context.insert(name.as_str(), value);
+            }
+        }
+        context
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_can_be_renamed() {
+        let enrichment = CfEnrichment::new()
+            .field(CfField::Colo)
+            .field(CfField::TlsVersion)
+            .field_as(CfField::Colo, "datacenter");
+        assert_eq!(
+            enrichment.fields(),
+            [
+                (CfField::Colo, "datacenter".to_string()),
+                (CfField::TlsVersion, "tls_version".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Mapping of log levels to the methods of the JavaScript console.
+
+use log::Level;
+
+use crate::platform;
+use crate::sink::{Entry, Sink};
+
+#[cfg(target_arch = "wasm32")]
+use crate::format;
+
+#[cfg(target_arch = "wasm32")]
+use worker::js_sys::Array;
+
+#[cfg(target_arch = "wasm32")]
+use worker::wasm_bindgen::JsValue;
+
+#[cfg(target_arch = "wasm32")]
+use worker::worker_sys::web_sys::console;
+
+/// A method of the JavaScript `console` object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleMethod {
+    /// `console.debug`
+    Debug,
+    /// `console.log`
+    Log,
+    /// `console.info`
+    Info,
+    /// `console.warn`
+    Warn,
+    /// `console.error`
+    Error,
+    /// `console.trace`, which also writes the JavaScript stack
+    Trace,
+}
+
+impl ConsoleMethod {
+    /// Write a value with this method
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn call(self, payload: &JsValue) {
+        match self {
+            ConsoleMethod::Debug => console::debug_1(payload),
+            ConsoleMethod::Log => console::log_1(payload),
+            ConsoleMethod::Info => console::info_1(payload),
+            ConsoleMethod::Warn => console::warn_1(payload),
+            ConsoleMethod::Error => console::error_1(payload),
+            ConsoleMethod::Trace => console::trace_1(payload),
+        }
+    }
+
+    /// Write several values with this method, like `console.log(...args)`
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn call_with(self, args: &Array) {
+        match self {
+            ConsoleMethod::Debug => console::debug(args),
+            ConsoleMethod::Log => console::log(args),
+            ConsoleMethod::Info => console::info(args),
+            ConsoleMethod::Warn => console::warn(args),
+            ConsoleMethod::Error => console::error(args),
+            ConsoleMethod::Trace => console::trace(args),
+        }
+    }
+}
+
+/// The console method used for each log level, by default `console.error`, `console.warn`,
+/// `console.info`, `console.debug` and `console.log` from `Error` to `Trace`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConsoleMethods([ConsoleMethod; 5]);
+
+impl Default for ConsoleMethods {
+    fn default() -> Self {
+        ConsoleMethods([
+            ConsoleMethod::Error,
+            ConsoleMethod::Warn,
+            ConsoleMethod::Info,
+            ConsoleMethod::Debug,
+            ConsoleMethod::Log,
+        ])
+    }
+}
+
+impl ConsoleMethods {
+    pub(crate) fn get(&self, level: Level) -> ConsoleMethod {
+        self.0[level as usize - 1]
+    }
+
+    pub(crate) fn set(&mut self, level: Level, method: ConsoleMethod) {
+        self.0[level as usize - 1] = method;
+    }
+}
+
+/// Sink writing to the JavaScript console, installed by default
+///
+/// Outside of `wasm32` the formatted entries are written to stderr instead, with JSON standing in
+/// for JavaScript objects.
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleSink {
+    methods: ConsoleMethods,
+    objects: bool,
+}
+
+impl ConsoleSink {
+    /// Create a console sink with the default method for each level
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the console method entries of the given level are written with, e.g.
+    /// [`ConsoleMethod::Trace`] for `Trace` to capture a JavaScript stack with every record
+    pub fn with_method(mut self, level: Level, method: ConsoleMethod) -> Self {
+        self.methods.set(level, method);
+        self
+    }
+
+    /// Pass native JavaScript objects to the console instead of formatted strings
+    pub fn with_objects(mut self, enabled: bool) -> Self {
+        self.objects = enabled;
+        self
+    }
+}
+
+impl ConsoleSink {
+    /// Write an entry rendered as a format string with `%c` directives and their CSS arguments
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn write_styled(&self, entry: &Entry, text: &str, css: &[String]) {
+        let args = Array::new();
+        args.push(&JsValue::from_str(text));
+        for css in css {
+            args.push(&JsValue::from_str(css));
+        }
+        self.methods.get(entry.level).call_with(&args);
+    }
+}
+
+impl Sink for ConsoleSink {
+    #[cfg(target_arch = "wasm32")]
+    fn write(&self, entry: &Entry, formatted: &str) {
+        let method = self.methods.get(entry.level);
+        if self.objects {
+            method.call(&format::object(entry));
+        } else {
+            platform::write(method, formatted);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write(&self, entry: &Entry, formatted: &str) {
+        platform::write(self.methods.get(entry.level), formatted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_map_to_console_methods() {
+        let sink = ConsoleSink::new().with_method(Level::Trace, ConsoleMethod::Trace);
+        let methods: Vec<_> = [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .map(|level| sink.methods.get(level))
+        .collect();
+        assert_eq!(
+            methods,
+            [
+                ConsoleMethod::Error,
+                ConsoleMethod::Warn,
+                ConsoleMethod::Info,
+                ConsoleMethod::Debug,
+                ConsoleMethod::Trace
+            ]
+        );
+    }
+}
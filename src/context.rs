@@ -0,0 +1,340 @@
+//! Request scoped context attached to every record logged while it is active.
+//!
+//! Concurrent requests served by the same isolate interleave at every `.await`, so the context is
+//! bound to a future with [`RequestContext::scope`] and only active while that future is polled.
+
+use log::{Level, LevelFilter};
+use serde_json::Value;
+use worker::Request;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use crate::format::Fields;
+use crate::platform;
+use crate::ring::{RingBuffer, Trail};
+use crate::sink::Entry;
+use crate::INSTALLED;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<RefCell<RequestContext>>>> = const { RefCell::new(None) };
+}
+
+/// Fields describing the request records are logged in
+///
+/// ```rust,ignore
+/// use worker::*;
+/// use worker_logger::RequestContext;
+///
+/// #[event(fetch)]
+/// async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+///     RequestContext::from_request(&req)
+///         .scope(handle(req, env))
+///         .await
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    fields: Fields,
+    started: Option<u64>,
+    level: Option<LevelFilter>,
+    trail: Trail,
+}
+
+impl RequestContext {
+    /// Create an empty context, marking the start of the request
+    pub fn new() -> Self {
+        RequestContext {
+            started: Some(platform::now_millis()),
+            ..Self::default()
+        }
+    }
+
+    /// Create a context holding the `cf_ray`, a generated `request_id`, the `method` and the
+    /// `path` of a request
+    pub fn from_request(req: &Request) -> Self {
+        let mut context = Self::new();
+        if let Ok(Some(ray)) = req.headers().get("cf-ray") {
+            context.insert("cf_ray", ray);
+        }
+        context.insert("request_id", generate_request_id());
+        context.insert("method", req.method().to_string());
+        context.insert("path", req.path());
+        context
+    }
+
+    /// Add a field to the context
+    pub fn with_field<K: Into<String>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Add a field to the context, replacing any previous value of the same key
+    pub fn insert<K: Into<String>, V: Into<Value>>(&mut self, key: K, value: V) {
+        let key = key.into();
+        let value = value.into();
+        match self.fields.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((key, value)),
+        }
+    }
+
+    /// Let records down to the given level through while this context is active, regardless of
+    /// the filter of the logger. See `DebugOverride` for enabling this from a signed token.
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// The level overriding the filter of the logger, if any
+    pub fn level(&self) -> Option<LevelFilter> {
+        self.level
+    }
+
+    /// Get the value of a field
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// All fields of the context, in insertion order
+    pub fn fields(&self) -> &[(String, Value)] {
+        &self.fields
+    }
+
+    /// Run a future with this context active, the context is cleared once the future completes
+    pub fn scope<F: Future>(self, future: F) -> Scoped<F> {
+        Scoped {
+            context: Rc::new(RefCell::new(self)),
+            future: Box::pin(future),
+        }
+    }
+
+    /// Activate this context for synchronous code until the returned guard is dropped
+    pub fn enter(self) -> ContextGuard {
+        activate(Rc::new(RefCell::new(self)))
+    }
+}
+
+/// A future running with a [`RequestContext`] active, created by [`RequestContext::scope`]
+pub struct Scoped<F> {
+    context: Rc<RefCell<RequestContext>>,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let _guard = activate(this.context.clone());
+        this.future.as_mut().poll(cx)
+    }
+}
+
+/// Keeps a [`RequestContext`] active until dropped, created by [`RequestContext::enter`]
+#[must_use = "the context is deactivated when the guard is dropped"]
+pub struct ContextGuard {
+    previous: Option<Rc<RefCell<RequestContext>>>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+fn activate(context: Rc<RefCell<RequestContext>>) -> ContextGuard {
+    if let Some(level) = context.borrow().level {
+        crate::raise_max_level(level);
+    }
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(context));
+    ContextGuard { previous }
+}
+
+/// Add a field to the active context, returns `false` if no context is active
+pub fn insert<K: Into<String>, V: Into<Value>>(key: K, value: V) -> bool {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(context) => {
+            context.borrow_mut().insert(key, value);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Get a copy of the active context
+pub fn current() -> Option<RequestContext> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|context| context.borrow().clone())
+    })
+}
+
+/// Fields of the active context, empty if no context is active
+pub(crate) fn current_fields() -> Fields {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|context| context.borrow().fields.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Mark the active request as failed, writing out the records retained by the
+/// [`RingBuffer`](crate::RingBuffer) of the installed logger. Records logged afterwards are
+/// written out directly. Returns `false` if no context is active.
+pub fn mark_failed() -> bool {
+    if !is_active() {
+        return false;
+    }
+    let entries = take_trail();
+    if let Some(logger) = INSTALLED.get() {
+        for entry in &entries {
+            logger.emit(entry);
+        }
+    }
+    true
+}
+
+/// Retain an entry in the trail of the active context, entries logged without an active context
+/// are dropped. Returns the entry back if the request already failed, it should be written out
+/// instead.
+pub(crate) fn retain(entry: Entry, limits: &RingBuffer) -> Option<Entry> {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(context) => {
+            let mut context = context.borrow_mut();
+            if context.trail.failed {
+                return Some(entry);
+            }
+            context.trail.push(entry, limits);
+            None
+        }
+        None => None,
+    })
+}
+
+/// Take the records retained for the active request and mark it as failed
+pub(crate) fn take_trail() -> Vec<Entry> {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(context) => {
+            let mut context = context.borrow_mut();
+            context.trail.failed = true;
+            context.trail.take()
+        }
+        None => Vec::new(),
+    })
+}
+
+/// Time in milliseconds since the Unix epoch the active context was created at
+pub(crate) fn request_start() -> Option<u64> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|context| context.borrow().started)
+    })
+}
+
+/// Whether the active context lets records of a level through regardless of the filter
+pub(crate) fn overrides(level: Level) -> bool {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|context| context.borrow().level)
+            .is_some_and(|max| level <= max)
+    })
+}
+
+/// Whether a context is active
+pub(crate) fn is_active() -> bool {
+    CURRENT.with(|current| current.borrow().is_some())
+}
+
+fn generate_request_id() -> String {
+    format!(
+        "{:08x}{:08x}",
+        platform::random_u32(),
+        platform::random_u32()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+
+    /// Completes on the second poll, like an `.await` on a pending promise
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    async fn observe() -> (Option<Value>, Option<Value>) {
+        let before = current().and_then(|c| c.get("request_id").cloned());
+        YieldOnce(false).await;
+        let after = current().and_then(|c| c.get("request_id").cloned());
+        (before, after)
+    }
+
+    #[test]
+    fn interleaved_scopes_keep_their_context() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut a = RequestContext::new()
+            .with_field("request_id", "a")
+            .scope(observe());
+        let mut b = RequestContext::new()
+            .with_field("request_id", "b")
+            .scope(observe());
+        assert!(Pin::new(&mut a).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut b).poll(&mut cx).is_pending());
+        assert!(current().is_none());
+        let a = Pin::new(&mut a).poll(&mut cx);
+        let b = Pin::new(&mut b).poll(&mut cx);
+        let (a_before, a_after) = match a {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("scope a did not complete"),
+        };
+        let (b_before, b_after) = match b {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("scope b did not complete"),
+        };
+        assert_eq!(a_before, Some("a".into()));
+        assert_eq!(a_after, Some("a".into()));
+        assert_eq!(b_before, Some("b".into()));
+        assert_eq!(b_after, Some("b".into()));
+    }
+
+    #[test]
+    fn guard_restores_previous_context() {
+        let outer = RequestContext::new().with_field("level", "outer").enter();
+        {
+            let _inner = RequestContext::new().with_field("level", "inner").enter();
+            assert!(insert("extra", 1));
+            let context = current().unwrap();
+            assert_eq!(context.get("level"), Some(&"inner".into()));
+            assert_eq!(context.get("extra"), Some(&1.into()));
+        }
+        assert_eq!(current().unwrap().get("level"), Some(&"outer".into()));
+        drop(outer);
+        assert!(current().is_none());
+        assert!(!insert("extra", 1));
+    }
+}
@@ -0,0 +1,186 @@
+//! Per-request filter overrides enabled by a signed token.
+
+use hmac::Mac;
+use log::LevelFilter;
+use worker::{Env as WorkerEnv, Request};
+
+use crate::context::RequestContext;
+use crate::signing::{self, HmacSha256};
+use crate::{platform, Error};
+
+use std::fmt;
+
+/// Default header carrying the token
+const DEFAULT_HEADER: &str = "x-log-override";
+
+/// Lets a single request log at a more verbose level when it carries a valid signed token
+///
+/// Tokens have the form `level.expiry.signature`, where `expiry` is a Unix timestamp in seconds and
+/// `signature` is the hex encoded HMAC-SHA256 of `level.expiry` keyed with a Worker secret. They
+/// are read from the `x-log-override` header by default, and can be created with
+/// [`DebugOverride::sign`].
+///
+/// ```rust,ignore
+/// use worker::*;
+/// use worker_logger::{DebugOverride, RequestContext};
+///
+/// #[event(fetch)]
+/// async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+///     let context = DebugOverride::from_env(&env, "LOG_OVERRIDE_KEY")?
+///         .query("log_override")
+///         .apply(&req, RequestContext::from_request(&req));
+///     context.scope(handle(req, env)).await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct DebugOverride {
+    key: Vec<u8>,
+    header: Option<String>,
+    query: Option<String>,
+    max_level: LevelFilter,
+}
+
+impl fmt::Debug for DebugOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key
+        f.debug_struct("DebugOverride")
+            .field("header", &self.header)
+            .field("query", &self.query)
+            .field("max_level", &self.max_level)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DebugOverride {
+    /// Verify tokens with the given key, which must not be empty since anyone could sign tokens
+    /// with an empty key
+    pub fn new<K: Into<Vec<u8>>>(key: K) -> Result<Self, Error> {
+        let key = key.into();
+        if key.is_empty() {
+            return Err(Error::EmptyKey);
+        }
+        Ok(DebugOverride {
+            key,
+            header: Some(DEFAULT_HEADER.to_string()),
+            query: None,
+            max_level: LevelFilter::Trace,
+        })
+    }
+
+    /// Verify tokens with the key stored in a Worker secret, which must not be empty
+    pub fn from_env<S: AsRef<str>>(env: &WorkerEnv, secret_name: S) -> Result<Self, Error> {
+        Self::new(env.secret(secret_name.as_ref())?.to_string())
+    }
+
+    /// Read the token from the given header, `x-log-override` by default
+    pub fn header<S: Into<String>>(mut self, name: S) -> Self {
+        self.header = Some(name.into());
+        self
+    }
+
+    /// Read the token from the given query parameter if the header is missing, disabled by default
+    pub fn query<S: Into<String>>(mut self, name: S) -> Self {
+        self.query = Some(name.into());
+        self
+    }
+
+    /// Set the most verbose level a token can enable, `trace` by default
+    pub fn max_level(mut self, level: LevelFilter) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Create a token enabling `level` until `expiry`, in seconds since the Unix epoch
+    pub fn sign(&self, level: LevelFilter, expiry: u64) -> String {
+        let payload = format!("{}.{}", level.as_str().to_ascii_lowercase(), expiry);
+        let signature = signing::hex(&self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Verify a token, returning the level it enables. Tokens that are malformed, expired or signed
+    /// with another key are rejected.
+    pub fn verify(&self, token: &str) -> Option<LevelFilter> {
+        self.verify_at(token, platform::now_millis() / 1000)
+    }
+
+    fn verify_at(&self, token: &str, now: u64) -> Option<LevelFilter> {
+        let (payload, signature) = token.trim().rsplit_once('.')?;
+        let (level, expiry) = payload.split_once('.')?;
+        let signature = decode_hex(signature)?;
+        self.mac(payload).verify_slice(&signature).ok()?;
+        if expiry.parse::<u64>().ok()? < now {
+            return None;
+        }
+        let level = level.parse::<LevelFilter>().ok()?;
+        Some(level.min(self.max_level))
+    }
+
+    /// Read and verify the token carried by a request
+    pub fn verify_request(&self, req: &Request) -> Option<LevelFilter> {
+        let from_header = self
+            .header
+            .as_deref()
+            .and_then(|name| req.headers().get(name).ok().flatten());
+        let token = from_header.or_else(|| {
+            let name = self.query.as_deref()?;
+            let url = req.url().ok()?;
+            let token = url
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned());
+            token
+        })?;
+        self.verify(&token)
+    }
+
+    /// Override the level of a request context if the request carries a valid token
+    pub fn apply(&self, req: &Request, context: RequestContext) -> RequestContext {
+        match self.verify_request(req) {
+            Some(level) => context.with_level(level),
+            None => context,
+        }
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        signing::mac(&self.key, payload)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` also takes a sign, so that `+a` would decode like `0a`
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_verified() {
+        let signer = DebugOverride::new("secret").unwrap();
+        let token = signer.sign(LevelFilter::Debug, 2000);
+        assert!(token.starts_with("debug.2000."));
+        assert_eq!(signer.verify_at(&token, 1000), Some(LevelFilter::Debug));
+        assert_eq!(signer.verify_at(&token, 2001), None);
+        assert_eq!(
+            DebugOverride::new("other").unwrap().verify_at(&token, 1000),
+            None
+        );
+        let forged = token.replacen("debug", "trace", 1);
+        assert_eq!(signer.verify_at(&forged, 1000), None);
+        assert_eq!(signer.verify_at("debug.2000.zz", 1000), None);
+        assert_eq!(decode_hex("+a"), None);
+        assert_eq!(decode_hex("0a"), Some(vec![10]));
+        let capped = DebugOverride::new("secret")
+            .unwrap()
+            .max_level(LevelFilter::Info);
+        assert_eq!(capped.verify_at(&token, 1000), Some(LevelFilter::Info));
+        assert!(matches!(DebugOverride::new(""), Err(Error::EmptyKey)));
+    }
+}
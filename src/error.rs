@@ -0,0 +1,64 @@
+//! Errors reported while installing the logger.
+
+use log::SetLoggerError;
+use worker::Error as WorkerError;
+
+use crate::filter::FilterIssue;
+
+use std::fmt;
+
+/// Errors that can occur while installing or configuring the logger
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A logger has already been installed, e.g. by a previous request in the same isolate
+    SetLogger(SetLoggerError),
+    /// The environment variable holding the filter could not be read
+    Env(WorkerError),
+    /// Buffered records could not be shipped to the collector
+    Flush(WorkerError),
+    /// The filter string has invalid directives, see [`parse_filter`](crate::parse_filter)
+    Filter(Vec<FilterIssue>),
+    /// A key used for signing or hashing is empty, e.g. because the secret holding it is unset
+    EmptyKey,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SetLogger(e) => write!(f, "logger installation failed: {}", e),
+            Error::Env(e) => write!(f, "failed to read logger configuration: {}", e),
+            Error::Flush(e) => write!(f, "failed to ship logs: {}", e),
+            Error::Filter(issues) => {
+                write!(f, "invalid log filter")?;
+                for (i, issue) in issues.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, issue)?;
+                }
+                Ok(())
+            }
+            Error::EmptyKey => write!(f, "the signing key is empty"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SetLogger(e) => Some(e),
+            Error::Env(e) | Error::Flush(e) => Some(e),
+            Error::Filter(_) | Error::EmptyKey => None,
+        }
+    }
+}
+
+impl From<SetLoggerError> for Error {
+    fn from(e: SetLoggerError) -> Self {
+        Error::SetLogger(e)
+    }
+}
+
+impl From<WorkerError> for Error {
+    fn from(e: WorkerError) -> Self {
+        Error::Env(e)
+    }
+}
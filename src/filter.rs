@@ -0,0 +1,387 @@
+//! Filtering of log records by level and target.
+//!
+//! Filter strings use the directive syntax of `env_logger`: a comma separated list of `level`,
+//! `target` or `target=level` directives, optionally followed by `/substring` to only keep records
+//! whose message contains `substring`.
+
+use log::{Level, LevelFilter, Metadata, Record};
+
+use crate::console::ConsoleMethod;
+use crate::platform;
+
+use std::fmt;
+
+/// A level applying to records whose target starts with `name`, or to all records if `name` is
+/// `None`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directive {
+    name: Option<String>,
+    level: LevelFilter,
+}
+
+/// A filter checking the level and target of records, and optionally their message
+#[derive(Debug)]
+pub(crate) struct Filter {
+    /// Sorted by name length, so that the most specific directive is found last
+    directives: Vec<Directive>,
+    message: Option<String>,
+}
+
+impl Filter {
+    /// The most verbose level this filter lets through
+    pub(crate) fn filter(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|directive| directive.level)
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    pub(crate) fn enabled(&self, metadata: &Metadata) -> bool {
+        self.directive(metadata.target())
+            .is_some_and(|directive| metadata.level() <= directive.level)
+    }
+
+    /// Whether a record would be enabled if the level of its directive was raised to `level`.
+    /// Targets turned off, or not matched by any directive, stay disabled.
+    pub(crate) fn enabled_up_to(&self, metadata: &Metadata, level: LevelFilter) -> bool {
+        self.directive(metadata.target()).is_some_and(|directive| {
+            directive.level != LevelFilter::Off && metadata.level() <= directive.level.max(level)
+        })
+    }
+
+    /// The directive applying to a target, the longest matching name wins
+    fn directive(&self, target: &str) -> Option<&Directive> {
+        self.directives.iter().rev().find(|directive| {
+            directive
+                .name
+                .as_deref()
+                .is_none_or(|name| target.starts_with(name))
+        })
+    }
+
+    pub(crate) fn matches(&self, record: &Record) -> bool {
+        self.enabled(record.metadata()) && self.matches_message(record)
+    }
+
+    /// Whether a record passes the message filter, if any
+    pub(crate) fn matches_message(&self, record: &Record) -> bool {
+        match &self.message {
+            Some(message) => record.args().to_string().contains(message.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// A problem found in a filter string by [`parse_filter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterIssue {
+    /// The directive the problem was found in, empty for problems of the whole string
+    pub directive: String,
+    /// What is wrong with the directive
+    pub kind: FilterIssueKind,
+}
+
+/// The kinds of problems [`parse_filter`] reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FilterIssueKind {
+    /// The level is not one of `off`, `error`, `warn`, `info`, `debug` or `trace`, the directive
+    /// is ignored
+    UnknownLevel(String),
+    /// The directive has a level but no target, e.g. `=debug`, it is ignored
+    EmptyTarget,
+    /// A directive for the same target appeared earlier, it is replaced by this one
+    Duplicate,
+    /// No directive sets a default level, so records of other targets are discarded. This is
+    /// often a misspelled level, which is read as a target.
+    MissingDefault,
+    /// No directive sets a default level and every directive is a bare word, e.g. `debg`. This is
+    /// almost always a misspelled level, so unlike [`FilterIssueKind::MissingDefault`] it is an
+    /// error. The words are still read as targets.
+    UnknownDefault,
+    /// The string holds no valid directive, info is used instead
+    Empty,
+}
+
+impl FilterIssue {
+    /// Whether the issue makes the logger ignore part of the filter string, as opposed to a
+    /// warning about a filter that is valid but likely not what was intended
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.kind,
+            FilterIssueKind::UnknownLevel(_)
+                | FilterIssueKind::UnknownDefault
+                | FilterIssueKind::EmptyTarget
+                | FilterIssueKind::Empty
+        )
+    }
+}
+
+impl fmt::Display for FilterIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FilterIssueKind::UnknownLevel(level) => write!(
+                f,
+                "unknown level {:?} in log directive {:?}, the directive is ignored",
+                level, self.directive
+            ),
+            FilterIssueKind::EmptyTarget => write!(
+                f,
+                "empty target in log directive {:?}, the directive is ignored",
+                self.directive
+            ),
+            FilterIssueKind::Duplicate => write!(
+                f,
+                "duplicate log directive {:?} replaces an earlier one",
+                self.directive
+            ),
+            FilterIssueKind::MissingDefault => write!(
+                f,
+                "no default level in log filter, only records of {:?} are logged",
+                self.directive
+            ),
+            FilterIssueKind::UnknownDefault => write!(
+                f,
+                "no default level in log filter {:?}, is it a misspelled level? Only records of \
+                 these targets are logged",
+                self.directive
+            ),
+            FilterIssueKind::Empty => write!(f, "log filter is empty, fallback to info"),
+        }
+    }
+}
+
+/// Check a filter string, returning the problems found in it
+///
+/// ```rust
+/// use worker_logger::{parse_filter, FilterIssueKind};
+///
+/// let issues = parse_filter("info,app=verbose");
+/// assert_eq!(issues[0].kind, FilterIssueKind::UnknownLevel("verbose".to_string()));
+/// assert!(issues[0].is_error());
+/// ```
+pub fn parse_filter(init_string: &str) -> Vec<FilterIssue> {
+    parse_with_issues(init_string).1
+}
+
+/// Parse a filter string, warning about the problems found in it. Falls back to info if it holds
+/// no valid directive.
+pub(crate) fn parse(init_string: &str) -> Filter {
+    let (filter, issues) = parse_with_issues(init_string);
+    warn(&issues);
+    filter
+}
+
+/// Parse a filter string like [`parse`], without writing anything
+pub(crate) fn parse_quietly(init_string: &str) -> Filter {
+    parse_with_issues(init_string).0
+}
+
+/// Write problems found in a filter string to the console
+pub(crate) fn warn(issues: &[FilterIssue]) {
+    for issue in issues {
+        platform::write(ConsoleMethod::Warn, &issue.to_string());
+    }
+}
+
+fn parse_with_issues(init_string: &str) -> (Filter, Vec<FilterIssue>) {
+    let (directives, message) = match init_string.split_once('/') {
+        Some((directives, message)) => (directives, Some(message.to_string())),
+        None => (init_string, None),
+    };
+    let mut parsed: Vec<Directive> = Vec::new();
+    let mut issues = Vec::new();
+    // Whether every directive is a bare word, i.e. a target without a level
+    let mut bare_words = true;
+    let issue = |directive: &str, kind| FilterIssue {
+        directive: directive.to_string(),
+        kind,
+    };
+    for directive in directives.split(',').map(str::trim) {
+        if directive.is_empty() {
+            continue;
+        }
+        let parsed_directive = match directive.split_once('=') {
+            Some((name, _)) if name.trim().is_empty() => {
+                issues.push(issue(directive, FilterIssueKind::EmptyTarget));
+                continue;
+            }
+            Some((name, level)) => match level.trim().parse() {
+                Ok(level) => {
+                    bare_words = false;
+                    Directive {
+                        name: Some(name.trim().to_string()),
+                        level,
+                    }
+                }
+                Err(_) => {
+                    let level = FilterIssueKind::UnknownLevel(level.trim().to_string());
+                    issues.push(issue(directive, level));
+                    continue;
+                }
+            },
+            None => match directive.parse() {
+                Ok(level) => {
+                    bare_words = false;
+                    Directive { name: None, level }
+                }
+                Err(_) => Directive {
+                    name: Some(directive.to_string()),
+                    level: LevelFilter::Trace,
+                },
+            },
+        };
+        if parsed.iter().any(|d| d.name == parsed_directive.name) {
+            issues.push(issue(directive, FilterIssueKind::Duplicate));
+            parsed.retain(|d| d.name != parsed_directive.name);
+        }
+        parsed.push(parsed_directive);
+    }
+    if parsed.is_empty() {
+        issues.push(issue(init_string, FilterIssueKind::Empty));
+        parsed.push(Directive {
+            name: None,
+            level: Level::Info.to_level_filter(),
+        });
+    } else if parsed.iter().all(|directive| directive.name.is_some()) {
+        let kind = if bare_words {
+            FilterIssueKind::UnknownDefault
+        } else {
+            FilterIssueKind::MissingDefault
+        };
+        issues.push(issue(directives.trim(), kind));
+    }
+    parsed.sort_by_key(|directive| directive.name.as_ref().map_or(0, String::len));
+    let filter = Filter {
+        directives: parsed,
+        message,
+    };
+    (filter, issues)
+}
+
+/// Replace the default level of a filter string, keeping its per-target directives and message
+/// filter
+pub(crate) fn with_level(init_string: &str, level: LevelFilter) -> String {
+    let (directives, message) = match init_string.split_once('/') {
+        Some((directives, message)) => (directives, Some(message)),
+        None => (init_string, None),
+    };
+    let mut out = level.as_str().to_ascii_lowercase();
+    for directive in directives.split(',').map(str::trim) {
+        if !directive.is_empty() && directive.parse::<LevelFilter>().is_err() {
+            out.push(',');
+            out.push_str(directive);
+        }
+    }
+    if let Some(message) = message {
+        out.push('/');
+        out.push_str(message);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(filter: &Filter, target: &str, level: Level) -> bool {
+        filter.enabled(&Metadata::builder().target(target).level(level).build())
+    }
+
+    #[test]
+    fn level_replaces_default_directive() {
+        assert_eq!(with_level("info", LevelFilter::Debug), "debug");
+        assert_eq!(
+            with_level("warn,app::db=trace, other/timeout", LevelFilter::Error),
+            "error,app::db=trace,other/timeout"
+        );
+        assert_eq!(with_level("", LevelFilter::Off), "off");
+    }
+
+    #[test]
+    fn most_specific_directive_wins() {
+        let filter = parse("warn,app=info,app::db=trace,noisy=off");
+        assert_eq!(filter.filter(), LevelFilter::Trace);
+        assert!(enabled(&filter, "other", Level::Warn));
+        assert!(!enabled(&filter, "other", Level::Info));
+        assert!(enabled(&filter, "app::http", Level::Info));
+        assert!(!enabled(&filter, "app::http", Level::Debug));
+        assert!(enabled(&filter, "app::db", Level::Trace));
+        assert!(!enabled(&filter, "noisy", Level::Error));
+    }
+
+    #[test]
+    fn levels_can_be_raised_per_directive() {
+        let filter = parse("info,noisy=off,app::db=warn");
+        let enabled = |target, level: Level| {
+            let metadata = Metadata::builder().target(target).level(level).build();
+            filter.enabled_up_to(&metadata, LevelFilter::Debug)
+        };
+        assert!(enabled("other", Level::Debug));
+        assert!(!enabled("other", Level::Trace));
+        assert!(enabled("app::db", Level::Debug));
+        assert!(!enabled("noisy", Level::Debug));
+        assert!(!enabled("noisy", Level::Error));
+        assert!(!parse("app").enabled_up_to(
+            &Metadata::builder()
+                .target("other")
+                .level(Level::Error)
+                .build(),
+            LevelFilter::Debug
+        ));
+    }
+
+    #[test]
+    fn targets_without_default_are_exclusive() {
+        let filter = parse("app");
+        assert!(enabled(&filter, "app", Level::Trace));
+        assert!(!enabled(&filter, "other", Level::Error));
+        let filter = parse("app=nope");
+        assert_eq!(filter.filter(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn issues_are_reported() {
+        let kinds = |init_string| -> Vec<FilterIssueKind> {
+            parse_filter(init_string)
+                .into_iter()
+                .map(|issue| issue.kind)
+                .collect()
+        };
+        assert_eq!(kinds("info,app::db=trace/timeout"), []);
+        assert_eq!(
+            kinds("info,=debug,app=verbose,info"),
+            [
+                FilterIssueKind::EmptyTarget,
+                FilterIssueKind::UnknownLevel("verbose".to_string()),
+                FilterIssueKind::Duplicate
+            ]
+        );
+        assert_eq!(kinds("debg"), [FilterIssueKind::UnknownDefault]);
+        assert!(parse_filter("debg")[0].is_error());
+        assert_eq!(kinds("app,db=debug"), [FilterIssueKind::MissingDefault]);
+        assert_eq!(kinds(""), [FilterIssueKind::Empty]);
+        let issue = &parse_filter("app=verbose")[0];
+        assert!(issue.is_error());
+        assert_eq!(
+            issue.to_string(),
+            "unknown level \"verbose\" in log directive \"app=verbose\", the directive is ignored"
+        );
+    }
+
+    #[test]
+    fn message_filter_is_a_substring() {
+        let filter = parse("info/timeout");
+        let record = |message| {
+            filter.matches(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Info)
+                    .build(),
+            )
+        };
+        assert!(record("upstream timeout after 30s"));
+        assert!(!record("upstream ok"));
+    }
+}
@@ -0,0 +1,428 @@
+//! Rendering of log records into their output representation.
+
+use log::kv::{self, Key, Value as KvValue, VisitSource};
+use log::Record;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::sink::Entry;
+use crate::style::Style;
+use crate::{context, platform};
+
+#[cfg(target_arch = "wasm32")]
+use worker::js_sys::{Array, Object, Reflect};
+
+#[cfg(target_arch = "wasm32")]
+use worker::wasm_bindgen::JsValue;
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Output format of the logger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human readable lines, e.g. `[time level file:line] message`
+    #[default]
+    Text,
+    /// One JSON object per record, with `timestamp`, `level`, `target`, `module_path`, `file`,
+    /// `line` and `message` keys, plus a nested `fields` object holding the record's key-values
+    Json,
+    /// A native JavaScript object passed directly to the console, so that the fields are indexed
+    /// by Workers Logs. Uses the same keys as [`OutputFormat::Json`].
+    Object,
+}
+
+/// How timestamps are written in text output
+///
+/// JSON output always uses ISO 8601, as does the `timestamp` key of JavaScript objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TimestampFormat {
+    /// ISO 8601 in UTC with millisecond precision, e.g. `2024-01-01T12:00:00.000Z`
+    #[default]
+    Iso8601,
+    /// Milliseconds since the Unix epoch, e.g. `1704110400000`
+    EpochMillis,
+    /// Milliseconds since the active [`RequestContext`](crate::RequestContext) was created, e.g.
+    /// `+12ms`. Missing outside of a request.
+    SinceRequestStart,
+    /// Milliseconds since the logger was built, which is close to when the isolate started if it is
+    /// built on startup, e.g. `+3012ms`
+    SinceIsolateStart,
+    /// JavaScript's `Date.prototype.toString`, e.g. `Mon Jan 01 2024 12:00:00 GMT+0000
+    /// (Coordinated Universal Time)`. ISO 8601 outside of `wasm32`.
+    DateString,
+    /// No timestamp at all, records are not timestamped
+    None,
+}
+
+impl TimestampFormat {
+    /// Parse the name of a format as used in templates, e.g. `{time:iso}`
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "iso" => TimestampFormat::Iso8601,
+            "epoch" => TimestampFormat::EpochMillis,
+            "request" => TimestampFormat::SinceRequestStart,
+            "isolate" => TimestampFormat::SinceIsolateStart,
+            "date" => TimestampFormat::DateString,
+            _ => return None,
+        })
+    }
+
+    /// Render a timestamp in milliseconds since the Unix epoch, for a logger built at `built`
+    pub(crate) fn render(self, millis: u64, built: u64) -> Option<String> {
+        match self {
+            TimestampFormat::Iso8601 => Some(iso8601(millis)),
+            TimestampFormat::EpochMillis => Some(millis.to_string()),
+            TimestampFormat::SinceRequestStart => {
+                context::request_start().map(|start| format!("+{}ms", millis.saturating_sub(start)))
+            }
+            TimestampFormat::SinceIsolateStart => {
+                Some(format!("+{}ms", millis.saturating_sub(built)))
+            }
+            TimestampFormat::DateString => Some(platform::date_string(millis)),
+            TimestampFormat::None => None,
+        }
+    }
+}
+
+/// Key-value pairs attached to a record, in the order they were written
+pub(crate) type Fields = Vec<(String, Value)>;
+
+struct CollectFields(Fields);
+
+impl<'kvs> VisitSource<'kvs> for CollectFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), kv::Error> {
+        // Keep numbers, booleans and serde values typed, only stringify what serde can't handle
+        let value =
+            serde_json::to_value(&value).unwrap_or_else(|_| Value::String(value.to_string()));
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+/// Collect the key-values of a record
+pub(crate) fn fields(record: &Record) -> Fields {
+    let mut collect = CollectFields(Vec::new());
+    // The visitor itself never fails
+    let _ = record.key_values().visit(&mut collect);
+    collect.0
+}
+
+/// Serializes fields as a map while preserving their order
+struct FieldMap<'a>(&'a [(String, Value)]);
+
+impl Serialize for FieldMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Append fields as `key=value` pairs, quoting values that would be ambiguous otherwise
+pub(crate) fn write_fields(out: &mut String, fields: &[(String, Value)]) {
+    for (key, value) in fields {
+        let _ = match value {
+            Value::String(s) if needs_quotes(s) => write!(out, " {}={:?}", key, s),
+            Value::String(s) => write!(out, " {}={}", key, s),
+            other => write!(out, " {}={}", key, other),
+        };
+    }
+}
+
+/// Escape control characters, such as line breaks and the escape character starting ANSI
+/// sequences, so that text can't forge log lines or styling. Unicode line separators and
+/// bidirectional overrides, which can make text display differently from what was logged, are
+/// escaped too. Tabs are kept.
+pub(crate) fn escape_control(text: &str) -> Cow<'_, str> {
+    if !text.contains(needs_escape) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii_control() && c != '\t' => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c if needs_escape(c) => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Whether [`escape_control`] escapes a character
+fn needs_escape(c: char) -> bool {
+    (c.is_control() && c != '\t')
+        || matches!(
+            c,
+            // Line and paragraph separators
+            '\u{2028}' | '\u{2029}'
+            // Bidirectional embeddings, overrides and isolates
+            | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+        )
+}
+
+fn needs_quotes(value: &str) -> bool {
+    value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=')
+}
+
+/// Convert a JSON value into the equivalent JavaScript value
+#[cfg(target_arch = "wasm32")]
+fn to_js(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(*b),
+        Value::Number(n) => n.as_f64().map_or(JsValue::NULL, JsValue::from_f64),
+        Value::String(s) => JsValue::from_str(s),
+        Value::Array(values) => values.iter().map(to_js).collect::<Array>().into(),
+        Value::Object(map) => {
+            let object = Object::new();
+            for (key, value) in map {
+                let _ = Reflect::set(&object, &JsValue::from_str(key), &to_js(value));
+            }
+            object.into()
+        }
+    }
+}
+
+/// Options shared by all output formats
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Options {
+    /// Whether to include the file and line of records
+    pub(crate) location: bool,
+    /// How to write timestamps in text output
+    pub(crate) time: TimestampFormat,
+    /// How to style the text output, never `Style::Auto`
+    pub(crate) style: Style,
+    /// Whether to escape control characters in messages and key-values of text output
+    pub(crate) escape: bool,
+    /// When the logger was built, in milliseconds since the Unix epoch
+    pub(crate) built: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            location: true,
+            time: TimestampFormat::default(),
+            style: Style::default(),
+            escape: false,
+            built: 0,
+        }
+    }
+}
+
+/// Convert fields into a JavaScript object
+#[cfg(target_arch = "wasm32")]
+fn to_js_object(fields: &[(String, Value)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in fields {
+        let _ = Reflect::set(&object, &JsValue::from_str(key), &to_js(value));
+    }
+    object.into()
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    level: &'a str,
+    target: &'a str,
+    module_path: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+    message: &'a str,
+    #[serde(skip_serializing_if = "is_empty")]
+    fields: FieldMap<'a>,
+    #[serde(skip_serializing_if = "is_empty")]
+    context: FieldMap<'a>,
+}
+
+fn is_empty(fields: &FieldMap) -> bool {
+    fields.0.is_empty()
+}
+
+/// Format a timestamp as an ISO 8601 date in UTC with millisecond precision
+pub(crate) fn iso8601(millis: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil(millis);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        millis % 1000
+    )
+}
+
+/// Split a timestamp into year, month, day, hour, minute and second in UTC
+pub(crate) fn civil(millis: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let secs = millis / 1000;
+    let days = (secs / 86400) as i64;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    // Convert days since the epoch to a civil date, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, hour, minute, second)
+}
+
+/// Render an entry as a single line JSON object
+pub(crate) fn json(entry: &Entry) -> String {
+    let json_record = JsonRecord {
+        timestamp: entry.timestamp.map(iso8601),
+        level: entry.level.as_str(),
+        target: &entry.target,
+        module_path: entry.module_path.as_deref(),
+        file: entry.file.as_deref(),
+        line: entry.line,
+        message: &entry.message,
+        fields: FieldMap(&entry.fields),
+        context: FieldMap(&entry.context),
+    };
+    // Serializing strings, integers and JSON values cannot fail
+    serde_json::to_string(&json_record).unwrap_or_default()
+}
+
+/// Build a JavaScript object from an entry
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn object(entry: &Entry) -> JsValue {
+    let object = Object::new();
+    let set = |key: &str, value: JsValue| {
+        // Setting a property on a plain object never throws
+        let _ = Reflect::set(&object, &JsValue::from_str(key), &value);
+    };
+    if let Some(millis) = entry.timestamp {
+        set("timestamp", JsValue::from_str(&iso8601(millis)));
+    }
+    let optional = |value: Option<&str>| value.map_or(JsValue::NULL, JsValue::from_str);
+    set("level", JsValue::from_str(entry.level.as_str()));
+    set("target", JsValue::from_str(&entry.target));
+    set("module_path", optional(entry.module_path.as_deref()));
+    set("file", optional(entry.file.as_deref()));
+    set("line", entry.line.map_or(JsValue::NULL, JsValue::from));
+    set("message", JsValue::from_str(&entry.message));
+    if !entry.fields.is_empty() {
+        set("fields", to_js_object(&entry.fields));
+    }
+    if !entry.context.is_empty() {
+        set("context", to_js_object(&entry.context));
+    }
+    object.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn json_output_is_escaped() {
+        let record = Record::builder()
+            .args(format_args!("say \"hi\"\n"))
+            .level(Level::Warn)
+            .target("app")
+            .module_path(Some("app::handler"))
+            .file(Some("src/handler.rs"))
+            .line(Some(42))
+            .build();
+        let line = json(&Entry::new(&record, Some(1640995200000), Vec::new(), true));
+        assert_eq!(
+            line,
+            r#"{"timestamp":"2022-01-01T00:00:00.000Z","level":"WARN","target":"app","module_path":"app::handler","file":"src/handler.rs","line":42,"message":"say \"hi\"\n"}"#
+        );
+    }
+
+    #[test]
+    fn key_values_keep_their_types() {
+        let kvs: &[(&str, KvValue)] = &[
+            ("user_id", KvValue::from(42)),
+            ("admin", KvValue::from(false)),
+            ("tags", KvValue::from_serde(&["a", "b"])),
+            ("note", KvValue::from("two words")),
+        ];
+        let record = Record::builder()
+            .args(format_args!("login"))
+            .level(Level::Info)
+            .target("app")
+            .key_values(&kvs)
+            .build();
+        assert_eq!(
+            json(&Entry::new(&record, None, Vec::new(), true)),
+            r#"{"level":"INFO","target":"app","module_path":null,"file":null,"line":null,"message":"login","fields":{"user_id":42,"admin":false,"tags":["a","b"],"note":"two words"}}"#
+        );
+        let mut line = String::new();
+        write_fields(&mut line, &fields(&record));
+        assert_eq!(
+            line,
+            r#" user_id=42 admin=false tags=["a","b"] note="two words""#
+        );
+    }
+
+    #[test]
+    fn context_is_nested_in_json() {
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(Level::Info)
+            .target("app")
+            .build();
+        let context = vec![("request_id".to_string(), Value::from("abc"))];
+        assert_eq!(
+            json(&Entry::new(&record, None, context, true)),
+            r#"{"level":"INFO","target":"app","module_path":null,"file":null,"line":null,"message":"hello","context":{"request_id":"abc"}}"#
+        );
+    }
+
+    #[test]
+    fn timestamp_formats() {
+        let millis = 1700000000123;
+        let render = |format: TimestampFormat| format.render(millis, millis - 3012);
+        assert_eq!(
+            render(TimestampFormat::Iso8601).as_deref(),
+            Some("2023-11-14T22:13:20.123Z")
+        );
+        assert_eq!(
+            render(TimestampFormat::EpochMillis).as_deref(),
+            Some("1700000000123")
+        );
+        assert_eq!(
+            render(TimestampFormat::SinceIsolateStart).as_deref(),
+            Some("+3012ms")
+        );
+        assert_eq!(render(TimestampFormat::SinceRequestStart), None);
+        assert_eq!(render(TimestampFormat::None), None);
+        let _guard = crate::RequestContext::new().enter();
+        let start = context::request_start().unwrap();
+        assert_eq!(
+            TimestampFormat::SinceRequestStart
+                .render(start + 42, 0)
+                .as_deref(),
+            Some("+42ms")
+        );
+    }
+
+    #[test]
+    fn iso8601_timestamps() {
+        assert_eq!(iso8601(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(951782400123), "2000-02-29T00:00:00.123Z");
+        assert_eq!(iso8601(1700000000000), "2023-11-14T22:13:20.000Z");
+    }
+}
@@ -0,0 +1,83 @@
+//! Runtime reconfiguration of the installed logger.
+
+use log::LevelFilter;
+
+use crate::Logger;
+
+/// Handle changing the filter of the installed logger at runtime, obtained with
+/// [`crate::handle`]
+///
+/// ```rust
+/// use log::LevelFilter;
+///
+/// worker_logger::init_once("info,app::db=warn").unwrap();
+/// let handle = worker_logger::handle().unwrap();
+/// handle.set_level(LevelFilter::Debug);
+/// handle.reset();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Handle {
+    logger: &'static Logger,
+}
+
+impl Handle {
+    pub(crate) fn new(logger: &'static Logger) -> Self {
+        Handle { logger }
+    }
+
+    /// Set the default level, keeping the per-target directives
+    pub fn set_level(&self, level: LevelFilter) {
+        let directives = crate::filter::with_level(&self.directives(), level);
+        self.logger.update_filter(&directives);
+    }
+
+    /// Replace the whole filter string, using the same syntax as the string the logger was
+    /// initialized with
+    pub fn set_directives<S: AsRef<str>>(&self, directives: S) {
+        self.logger.update_filter(directives.as_ref());
+    }
+
+    /// The current filter string
+    pub fn directives(&self) -> String {
+        self.logger.read_directives()
+    }
+
+    /// The most verbose level the current filter lets through
+    pub fn level(&self) -> LevelFilter {
+        self.logger.read_filter().filter()
+    }
+
+    /// Restore the filter string the logger was installed with
+    pub fn reset(&self) {
+        self.logger.update_filter(&self.logger.initial_directives);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LoggerBuilder;
+
+    #[test]
+    fn filter_changes_and_resets() {
+        let logger = Box::leak(Box::new(LoggerBuilder::new().filter("warn").build()));
+        let handle = Handle::new(logger);
+        handle.set_level(LevelFilter::Debug);
+        assert_eq!(handle.directives(), "debug");
+        assert_eq!(handle.level(), LevelFilter::Debug);
+        handle.set_directives("error");
+        assert_eq!(handle.level(), LevelFilter::Error);
+        handle.reset();
+        assert_eq!(handle.directives(), "warn");
+        assert_eq!(handle.level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn level_keeps_target_directives() {
+        let logger = LoggerBuilder::new().filter("info,app::db=trace").build();
+        let handle = Handle::new(Box::leak(Box::new(logger)));
+        handle.set_level(LevelFilter::Error);
+        assert_eq!(handle.directives(), "error,app::db=trace");
+        assert_eq!(handle.level(), LevelFilter::Trace);
+    }
+}
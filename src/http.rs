@@ -0,0 +1,247 @@
+//! Shipping of buffered records to an HTTP collector.
+
+use worker::{Error as WorkerError, Method, Url};
+
+use crate::sink::{Entry, Sink};
+
+#[cfg(target_arch = "wasm32")]
+use worker::wasm_bindgen::JsValue;
+
+#[cfg(target_arch = "wasm32")]
+use worker::wasm_bindgen_futures::spawn_local;
+
+#[cfg(target_arch = "wasm32")]
+use worker::{Fetch, Headers, Request, RequestInit};
+
+#[cfg(target_arch = "wasm32")]
+use crate::console::ConsoleMethod;
+
+#[cfg(target_arch = "wasm32")]
+use crate::platform;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Default maximum number of records kept in the buffer
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// A request to the collector, built without the JavaScript runtime
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CollectorRequest {
+    pub(crate) method: Method,
+    pub(crate) url: Url,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: String,
+}
+
+impl CollectorRequest {
+    /// Turn it into a request for `fetch`
+    #[cfg(target_arch = "wasm32")]
+    fn into_request(self) -> Result<Request, WorkerError> {
+        let mut headers = Headers::new();
+        for (name, value) in &self.headers {
+            headers.set(name, value)?;
+        }
+        let mut init = RequestInit::new();
+        init.with_method(self.method)
+            .with_headers(headers)
+            .with_body(Some(JsValue::from_str(&self.body)));
+        Request::new_with_init(self.url.as_str(), &init)
+    }
+}
+
+/// Buffers records as JSON lines and POSTs them as NDJSON to a collector when flushed
+///
+/// Shipping is asynchronous, so it should be driven from `Context::wait_until` to keep it from
+/// delaying the response:
+///
+/// ```rust,ignore
+/// use worker_logger::{HttpSink, LoggerBuilder};
+///
+/// LoggerBuilder::new()
+///     .http_sink(HttpSink::new("https://logs.example.com/ingest".parse()?))
+///     .install()?;
+/// // At the end of the fetch handler
+/// ctx.wait_until(async {
+///     let _ = worker_logger::flush().await;
+/// });
+/// ```
+#[derive(Debug)]
+pub struct HttpSink {
+    url: Url,
+    headers: Vec<(String, String)>,
+    capacity: usize,
+    buffer: Mutex<VecDeque<String>>,
+}
+
+impl HttpSink {
+    /// Create a sink shipping records to the given URL
+    pub fn new(url: Url) -> Self {
+        HttpSink {
+            url,
+            headers: Vec::new(),
+            capacity: DEFAULT_CAPACITY,
+            buffer: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Add a header to every request sent to the collector, e.g. for authentication
+    pub fn with_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the maximum number of buffered records, the oldest records are dropped once it is
+    /// exceeded. Defaults to 1000.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    fn push(&self, line: String) {
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        if self.capacity == 0 {
+            return;
+        }
+        while buffer.len() >= self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(line);
+    }
+
+    /// Take all buffered records as an NDJSON body, `None` if the buffer is empty
+    pub fn take_batch(&self) -> Option<String> {
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.is_empty() {
+            return None;
+        }
+        let mut body = String::new();
+        for line in buffer.drain(..) {
+            body.push_str(&line);
+            body.push('\n');
+        }
+        Some(body)
+    }
+
+    /// Ship all buffered records to the collector
+    pub async fn flush(&self) -> Result<(), WorkerError> {
+        match self.take_batch() {
+            Some(body) => self.send(body).await,
+            None => Ok(()),
+        }
+    }
+
+    /// POST an NDJSON body to the collector
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) async fn send(&self, body: String) -> Result<(), WorkerError> {
+        ship(self.request(body).into_request()?).await
+    }
+
+    /// There is no `fetch` outside of `wasm32`, the batch is dropped with an error
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn send(&self, _body: String) -> Result<(), WorkerError> {
+        Err(WorkerError::RustError(format!(
+            "shipping logs to {} requires a wasm32 target",
+            self.url
+        )))
+    }
+
+    /// The POST request carrying an NDJSON body, with the configured headers after the content
+    /// type so that they can override it
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    fn request(&self, body: String) -> CollectorRequest {
+        let mut headers = vec![(
+            "content-type".to_string(),
+            "application/x-ndjson".to_string(),
+        )];
+        headers.extend(self.headers.iter().cloned());
+        CollectorRequest {
+            method: Method::Post,
+            url: self.url.clone(),
+            headers,
+            body,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn ship(request: Request) -> Result<(), WorkerError> {
+    let response = Fetch::Request(request).send().await?;
+    match response.status_code() {
+        200..=299 => Ok(()),
+        status => Err(WorkerError::RustError(format!(
+            "log collector responded with status {}",
+            status
+        ))),
+    }
+}
+
+impl Sink for HttpSink {
+    fn write(&self, entry: &Entry, _formatted: &str) {
+        self.push(entry.to_json());
+    }
+
+    /// Ships the buffered records in the background. Workers may cancel background tasks once the
+    /// response is sent, prefer passing [`crate::flush`] to `Context::wait_until` instead.
+    ///
+    /// Outside of `wasm32` the records are kept in the buffer, see [`HttpSink::take_batch`].
+    #[cfg(target_arch = "wasm32")]
+    fn flush(&self) {
+        if let Some(body) = self.take_batch() {
+            let request = self.request(body).into_request();
+            spawn_local(async move {
+                let result = match request {
+                    Ok(request) => ship(request).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    let message = format!("failed to ship logs: {}", e);
+                    platform::write(ConsoleMethod::Error, &message);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_are_ndjson() {
+        let sink = HttpSink::new("http://127.0.0.1:8787/".parse().unwrap()).with_capacity(2);
+        assert_eq!(sink.take_batch(), None);
+        sink.push(r#"{"message":"a"}"#.to_string());
+        sink.push(r#"{"message":"b"}"#.to_string());
+        sink.push(r#"{"message":"c"}"#.to_string());
+        assert_eq!(
+            sink.take_batch().as_deref(),
+            Some("{\"message\":\"b\"}\n{\"message\":\"c\"}\n")
+        );
+        assert_eq!(sink.take_batch(), None);
+    }
+
+    #[test]
+    fn requests_post_ndjson() {
+        let sink = HttpSink::new("https://logs.example.com/ingest".parse().unwrap())
+            .with_header("authorization", "Bearer abc");
+        sink.push(r#"{"message":"a"}"#.to_string());
+        let request = sink.request(sink.take_batch().unwrap());
+        assert_eq!(
+            request,
+            CollectorRequest {
+                method: Method::Post,
+                url: "https://logs.example.com/ingest".parse().unwrap(),
+                headers: vec![
+                    (
+                        "content-type".to_string(),
+                        "application/x-ndjson".to_string()
+                    ),
+                    ("authorization".to_string(), "Bearer abc".to_string()),
+                ],
+                body: "{\"message\":\"a\"}\n".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! A [`tracing_subscriber::Layer`] writing events with the formatting of a [`Logger`].
+
+use crate::format::Fields;
+use crate::{platform, Logger};
+use log::kv::Value as KvValue;
+use log::{Level, Log, Metadata, Record};
+use serde_json::{Number, Value};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use std::fmt;
+
+/// A `tracing` layer writing events to the worker console
+///
+/// Events are formatted and filtered by the wrapped [`Logger`] like records of the `log` crate,
+/// with their fields rendered as key-values. Entering a span the logger's filter enables opens a
+/// collapsible console group which is closed again when the span is exited.
+///
+/// ```rust,ignore
+/// use tracing_subscriber::prelude::*;
+/// use worker_logger::{Logger, WorkerLayer};
+///
+/// tracing_subscriber::registry()
+///     .with(WorkerLayer::new(Logger::new("debug")))
+///     .init();
+/// ```
+#[derive(Debug)]
+pub struct WorkerLayer {
+    logger: Logger,
+}
+
+impl WorkerLayer {
+    /// Create a layer writing events with the given logger
+    pub fn new(logger: Logger) -> Self {
+        WorkerLayer { logger }
+    }
+
+    /// Whether the logger's filter lets spans or events with this metadata through
+    fn enabled(&self, metadata: &tracing_core::Metadata<'_>) -> bool {
+        self.logger.enabled(
+            &Metadata::builder()
+                .level(to_level(metadata.level()))
+                .target(metadata.target())
+                .build(),
+        )
+    }
+}
+
+impl Default for WorkerLayer {
+    fn default() -> Self {
+        Self::new(Logger::builder().build())
+    }
+}
+
+impl From<Logger> for WorkerLayer {
+    fn from(logger: Logger) -> Self {
+        Self::new(logger)
+    }
+}
+
+/// Label of the console group opened when a span is entered, stored in the span's extensions
+struct GroupLabel {
+    label: String,
+    /// Whether the span was entered before, e.g. by an earlier poll of an instrumented future
+    entered: bool,
+}
+
+#[derive(Default)]
+struct CollectFields {
+    message: Option<String>,
+    fields: Fields,
+}
+
+impl CollectFields {
+    fn push(&mut self, field: &Field, value: Value) {
+        self.fields.push((field.name().to_string(), value));
+    }
+}
+
+impl Visit for CollectFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        let value = Number::from_f64(value).map_or_else(|| value.to_string().into(), Value::Number);
+        self.push(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.push(field, value.into());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.push(field, format!("{:?}", value).into());
+        }
+    }
+}
+
+fn to_level(level: &tracing_core::Level) -> Level {
+    match *level {
+        tracing_core::Level::ERROR => Level::Error,
+        tracing_core::Level::WARN => Level::Warn,
+        tracing_core::Level::INFO => Level::Info,
+        tracing_core::Level::DEBUG => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+impl<S> Layer<S> for WorkerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        // Spans the filter rejects are entered without opening a group
+        if !self.enabled(metadata) {
+            return;
+        }
+        let mut collect = CollectFields::default();
+        attrs.record(&mut collect);
+        let label = self.logger.span_label(metadata.name(), collect.fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(GroupLabel {
+                label,
+                entered: false,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let group = ctx.span(id).and_then(|span| {
+            let mut extensions = span.extensions_mut();
+            let group = extensions.get_mut::<GroupLabel>()?;
+            let reentered = std::mem::replace(&mut group.entered, true);
+            Some((group.label.clone(), reentered))
+        });
+        match group {
+            // stderr has no groups to close, the label is only written on the first enter
+            Some((_, true)) if cfg!(not(target_arch = "wasm32")) => {}
+            Some((label, _)) => platform::group(&label),
+            None => {}
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let grouped = ctx
+            .span(id)
+            .is_some_and(|span| span.extensions().get::<GroupLabel>().is_some());
+        if grouped {
+            platform::group_end();
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Skip collecting the fields of events the filter rejects anyway
+        if !self.enabled(metadata) {
+            return;
+        }
+        let mut collect = CollectFields::default();
+        event.record(&mut collect);
+        let message = collect.message.unwrap_or_default();
+        let key_values: Vec<(&str, KvValue)> = collect
+            .fields
+            .iter()
+            .map(|(key, value)| (key.as_str(), KvValue::from_serde(value)))
+            .collect();
+        self.logger.log(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(to_level(metadata.level()))
+                .target(metadata.target())
+                .module_path(metadata.module_path())
+                .file(metadata.file())
+                .line(metadata.line())
+                .key_values(&key_values)
+                .build(),
+        );
+    }
+}
@@ -24,131 +24,537 @@
 //! worker_logger::init_with_env(env, "LOG")?;
 //! ```
 //!
+//! Workers reuse isolates across requests, so a logger installed by a previous request may
+//! already be active. `init_once_with_env` installs the logger on the first call and only updates
+//! its filter afterwards, while the `try_init_*` functions report failures as an `Error`:
+//!
+//! ```rust,ignore
+//! worker_logger::init_once_with_env(&env, "LOG")?;
+//! ```
+//!
+//! Emit one JSON object per record instead of human readable lines
+//! (or use `OutputFormat::Object` to pass native JavaScript objects to the console, so that
+//! Workers Logs indexes their fields):
+//!
+//! ```rust
+//! use worker_logger::{Logger, OutputFormat};
+//! Logger::new("info").with_format(OutputFormat::Json).set_logger();
+//! ```
+//!
+//! For finer control, `LoggerBuilder` configures the filter, timestamps (ISO 8601 by default, or
+//! epoch milliseconds, time since the request or isolate started), source locations,
+//! colors, output format and the console method used for each level. Nothing global is touched
+//! until the logger is installed:
+//!
+//! ```rust
+//! use worker_logger::{LoggerBuilder, OutputFormat, TimestampFormat};
+//! LoggerBuilder::new()
+//!     .filter("debug")
+//!     .timestamp_format(TimestampFormat::SinceRequestStart)
+//!     .source_location(false)
+//!     .format(OutputFormat::Json)
+//!     .install()
+//!     .unwrap();
+//! ```
+//!
+//! Key-values from the `log` crate's `kv` API are rendered as `key=value` pairs after the message,
+//! or as a nested `fields` object in the structured formats, keeping numbers, booleans and
+//! serde values typed:
+//!
+//! ```rust
+//! log::info!(user_id = 42, admin = false; "login");
+//! ```
+//!
+//! Records logged while a `RequestContext` is active carry its fields (`cf_ray`, a generated
+//! `request_id`, `method` and `path` when created from a request). The context follows the
+//! future it is scoped to, so concurrent requests in one isolate don't mix up their fields:
+//!
+//! ```rust,ignore
+//! use worker_logger::RequestContext;
+//! RequestContext::from_request(&req).scope(handle(req, env)).await
+//! ```
+//!
+//! Output goes through sinks, with the console being the default one. Implement `Sink` to add
+//! your own destinations, or disable the console entirely:
+//!
+//! ```rust
+//! use worker_logger::{Entry, LoggerBuilder, Sink};
+//!
+//! struct Stdout;
+//!
+//! impl Sink for Stdout {
+//!     fn write(&self, _entry: &Entry, formatted: &str) {
+//!         println!("{}", formatted);
+//!     }
+//! }
+//!
+//! LoggerBuilder::new().console(false).sink(Stdout).install().unwrap();
+//! ```
+//!
+//! Records can also be shipped to your own collector. An `HttpSink` buffers them as JSON lines
+//! and `flush` POSTs the buffer as NDJSON, without delaying the response when driven from
+//! `Context::wait_until`:
+//!
+//! ```rust,ignore
+//! use worker_logger::{HttpSink, LoggerBuilder};
+//! LoggerBuilder::new()
+//!     .http_sink(HttpSink::new("https://logs.example.com/ingest".parse()?))
+//!     .install()?;
+//! // At the end of the fetch handler
+//! ctx.wait_until(async {
+//!     let _ = worker_logger::flush().await;
+//! });
+//! ```
+//!
+//! The filter of the installed logger can be changed at runtime through a handle:
+//!
+//! ```rust
+//! use log::LevelFilter;
+//! worker_logger::init_once("info,app::db=warn").unwrap();
+//! let handle = worker_logger::handle().unwrap();
+//! handle.set_level(LevelFilter::Debug);
+//! assert_eq!(handle.directives(), "debug,app::db=warn");
+//! handle.reset();
+//! ```
+//!
+//! The layout of text output can be changed with a template, parsed once when the logger is built:
+//!
+//! ```rust,ignore
+//! use worker_logger::{LoggerBuilder, Template};
+//! let template: Template = "{time} {level:5} {module}{?@{file}:{line}} {msg}{? {kv}}".parse()?;
+//! LoggerBuilder::new().template(template).install()?;
+//! ```
+//!
+//! Properties of the `cf` object of a request, such as the colo, country, ASN, TLS version or bot
+//! score, can be attached to every record of the request under names of your choice:
+//!
+//! ```rust,ignore
+//! use worker_logger::{CfEnrichment, CfField, RequestContext};
+//! let context = CfEnrichment::new()
+//!     .field(CfField::Colo)
+//!     .field(CfField::Asn)
+//!     .field_as(CfField::BotScore, "bot")
+//!     .apply(&req, RequestContext::from_request(&req));
+//! ```
+//!
+//! Requests can be logged once their handler completes, with the method, URL, status, response
+//! size, latency and colo of each request, at a level following the status class:
+//!
+//! ```rust,ignore
+//! use worker_logger::{AccessFormat, AccessLog};
+//! AccessLog::new()
+//!     .format(AccessFormat::Combined)
+//!     .serve(req, |req| handle(req, env))
+//!     .await
+//! ```
+//!
+//! Records below the filter level can be retained per request and only written out if the request
+//! fails, i.e. logs an `Error` record or calls `context::mark_failed`:
+//!
+//! ```rust
+//! use log::Level;
+//! use worker_logger::{LoggerBuilder, RingBuffer};
+//! LoggerBuilder::new()
+//!     .filter("info")
+//!     .ring_buffer(RingBuffer::new(Level::Debug))
+//!     .build();
+//! ```
+//!
+//! Secrets and personal data such as bearer tokens, JWTs, emails, card numbers and IP addresses
+//! can be scrubbed from messages, key-values and context fields before records are written out:
+//!
+//! ```rust
+//! use worker_logger::{LoggerBuilder, Redaction};
+//! LoggerBuilder::new()
+//!     .redaction(Redaction::new().deny_key("session_id"))
+//!     .build();
+//! ```
+//!
+//! With the `redact_hash` feature, redacted text can be replaced with a keyed hash instead, so that
+//! records about the same value can be correlated, using `Replacement::hash_from_env` with a
+//! Worker secret.
+//!
+//! Logged input can forge log lines or styling with line breaks, ANSI escape sequences or
+//! bidirectional overrides. Text output escapes them when enabled with `escape_control`:
+//!
+//! ```rust
+//! worker_logger::LoggerBuilder::new().escape_control(true).build();
+//! ```
+//!
+//! With the `testing` feature, emitted records can be asserted on in tests with the `testing`
+//! module:
+//!
+//! ```rust
+//! # #[cfg(feature = "testing")] {
+//! let logs = worker_logger::testing::capture();
+//! log::warn!("rate limited");
+//! logs.assert_logged(log::Level::Warn, "rate limited");
+//! # }
+//! ```
+//!
+//! Outside of `wasm32`, e.g. under `cargo test`, records are written to stderr and timestamped
+//! with the system clock, so code using the logger runs natively without a JavaScript runtime.
+//!
+//! # Filter syntax
+//!
+//! Filter strings are comma separated directives, using the same syntax as
+//! [`env_logger`](https://docs.rs/env_logger/latest/env_logger/#enabling-logging):
+//!
+//!  - `level` sets the default level, e.g. `info`
+//!  - `target=level` sets the level of records whose target starts with `target`, the longest
+//!    matching target wins, e.g. `warn,my_worker::db=trace`
+//!  - `target` alone enables every level for that target
+//!  - `/substring` at the end only keeps records whose message contains `substring`, e.g.
+//!    `debug/timeout`
+//!
+//! A string without any valid directive falls back to `info`. Problems in a filter string, such as
+//! unknown levels, are written to the console as warnings when the logger is installed or its
+//! filter is changed. They can be checked with `parse_filter`, and make `try_init_with_env` and
+//! `init_once_with_env` return an error. A string made only of targets, such as the misspelled
+//! level `debg`, has no default level and counts as an error too.
+//!
+//!
 //! # Features
 //!
-//!  - `env_logger_string`: Enables advanced logging filters. Uses the same syntax as
-//!    [`env_logger`](https://crates.io/crates/env_logger). For more details, please visit
-//!    <https://docs.rs/env_logger/latest/env_logger/#enabling-logging>.
-//!  - `color`: Enable colored output with [`colored`](https://crates.io/crates/colored).
+//!  - `env_logger_string`: No longer has any effect, kept for compatibility. Filter strings are
+//!    always parsed natively, see above.
+//!  - `color`: Enable colored output with [`colored`](https://crates.io/crates/colored). Output
+//!    can also be styled with CSS for the devtools inspector of `wrangler dev`, with or without
+//!    this feature, using `LoggerBuilder::style(Style::Css)`. `Style::from_env` picks the style from
+//!    a variable, e.g. CSS under `wrangler dev` only, and `Style::Auto` otherwise, which is plain on
+//!    `wasm32`.
+//!  - `debug_override`: Provides `DebugOverride`, raising the level of a single request carrying
+//!    an HMAC signed token, keyed with a Worker secret.
+//!  - `redact_hash`: Provides `Replacement::hash`, replacing redacted text with its HMAC-SHA256,
+//!    keyed with a Worker secret.
+//!  - `testing`: Provides the `testing` module, capturing emitted records for assertions. Meant to
+//!    be enabled in `dev-dependencies` only.
+//!  - `tracing`: Provides `WorkerLayer`, a
+//!    [`tracing-subscriber`](https://crates.io/crates/tracing-subscriber) layer formatting events
+//!    like the logger and rendering spans as collapsible console groups.
+//!  - `router`: Provides `LoggedRouter`, a `worker::Router` running every route with a
+//!    `RequestContext` holding the matched route pattern and parameters, and an `AccessLog`.
 
-use log::{set_max_level, Level, Metadata, Record};
-use worker::{console_debug, console_error, console_log, console_warn, Date};
-use worker::{Env as WorkerEnv, Error as WorkerError};
+mod access;
+mod builder;
+mod cf;
+mod console;
+pub mod context;
+mod error;
+mod filter;
+mod format;
+mod handle;
+mod http;
+mod platform;
+mod redact;
+mod ring;
+mod sink;
+mod style;
+mod template;
+
+#[cfg(feature = "debug_override")]
+mod debug_override;
+
+#[cfg(feature = "tracing")]
+mod layer;
 
-#[cfg(feature = "env_logger_string")]
-use env_logger::filter::{Builder, Filter};
+#[cfg(any(feature = "debug_override", feature = "redact_hash"))]
+mod signing;
 
-#[cfg(feature = "env_logger_string")]
-use log::set_boxed_logger;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-#[cfg(not(feature = "env_logger_string"))]
-use log::set_logger;
+#[cfg(feature = "router")]
+mod router;
+
+pub use access::{AccessFormat, AccessLog};
+pub use builder::LoggerBuilder;
+pub use cf::{CfEnrichment, CfField};
+pub use console::{ConsoleMethod, ConsoleSink};
+pub use context::RequestContext;
+pub use error::Error;
+pub use filter::{parse_filter, FilterIssue, FilterIssueKind};
+pub use format::{OutputFormat, TimestampFormat};
+pub use handle::Handle;
+pub use http::HttpSink;
+pub use redact::{Detector, Redaction, Replacement};
+pub use ring::RingBuffer;
+pub use sink::{Entry, Sink};
+pub use style::Style;
+pub use template::{Template, TemplateError};
+
+#[cfg(feature = "debug_override")]
+pub use debug_override::DebugOverride;
+
+#[cfg(feature = "tracing")]
+pub use layer::WorkerLayer;
+
+#[cfg(feature = "router")]
+pub use router::LoggedRouter;
+
+use log::{set_logger, set_max_level, Level, LevelFilter, Metadata, Record};
+use worker::{Env as WorkerEnv, Error as WorkerError};
 
-#[cfg(not(feature = "env_logger_string"))]
-use std::str::FromStr;
+use filter::Filter;
+use format::Options;
 
-#[cfg(feature = "color")]
-use colored::Colorize;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard};
 
-#[cfg(not(feature = "env_logger_string"))]
-static WORKER_LOGGER: Logger = Logger {};
+/// The logger installed by this crate, kept around so that later initializations can update it
+static INSTALLED: OnceLock<&'static Logger> = OnceLock::new();
 
 /// Main logger struct
-#[derive(Debug)]
 pub struct Logger {
-    #[cfg(feature = "env_logger_string")]
-    filter: Filter,
+    filter: RwLock<Filter>,
+    directives: RwLock<String>,
+    initial_directives: String,
+    format: OutputFormat,
+    options: Options,
+    template: Template,
+    console: Option<ConsoleSink>,
+    sinks: Vec<Box<dyn Sink>>,
+    http_sink: Option<Arc<HttpSink>>,
+    ring_buffer: Option<RingBuffer>,
+    redaction: Option<Redaction>,
+}
+
+impl fmt::Debug for Logger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Logger")
+            .field("filter", &self.filter)
+            .field("directives", &self.directives)
+            .field("format", &self.format)
+            .field("options", &self.options)
+            .field("template", &self.template)
+            .field("console", &self.console)
+            .field("sinks", &self.sinks.len())
+            .field("http_sink", &self.http_sink)
+            .field("ring_buffer", &self.ring_buffer)
+            .field("redaction", &self.redaction)
+            .finish()
+    }
 }
 
 impl Logger {
     /// Initialize the logger with a string
     pub fn new<S: AsRef<str>>(init_string: S) -> Self {
-        #[cfg(not(feature = "env_logger_string"))]
-        {
-            let level = Level::from_str(init_string.as_ref());
-            if let Err(ref e) = level {
-                console_debug!("Failed to parse log level string, fallback to info: {}", e);
-            }
-            set_max_level(level.unwrap_or(Level::Info).to_level_filter());
+        LoggerBuilder::new().filter(init_string).build()
+    }
+
+    /// Create a builder for configuring the logger
+    pub fn builder() -> LoggerBuilder {
+        LoggerBuilder::new()
+    }
+
+    /// Set the output format of the logger
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self.console = self
+            .console
+            .map(|console| console.with_objects(format == OutputFormat::Object));
+        self
+    }
+
+    /// Set the logger instance as the main logger, returning an error if a logger is already set
+    pub fn try_set_logger(self) -> Result<(), Error> {
+        let raw = Box::into_raw(Box::new(self));
+        // SAFETY: `raw` comes from `Box::into_raw` and is only freed if `set_logger` fails, in which
+        // case the log crate kept no reference to it. Otherwise it is leaked for good.
+        let logger: &'static Logger = unsafe { &*raw };
+        if let Err(e) = set_logger(logger) {
+            drop(unsafe { Box::from_raw(raw) });
+            return Err(e.into());
         }
+        filter::warn(&parse_filter(&logger.read_directives()));
+        set_max_level(logger.max_level(&logger.read_filter()));
         #[cfg(feature = "color")]
-        colored::control::set_override(true);
-        Logger {
-            #[cfg(feature = "env_logger_string")]
-            filter: Builder::new().parse(init_string.as_ref()).build(),
+        if logger.options.style == Style::Ansi {
+            colored::control::set_override(true);
         }
+        let _ = INSTALLED.set(logger);
+        Ok(())
     }
 
-    #[cfg(feature = "env_logger_string")]
     /// Set the logger instance as the main logger
     pub fn set_logger(self) {
-        set_max_level(self.filter.filter());
-        let result = set_boxed_logger(Box::new(self));
-        if let Err(e) = result {
-            console_error!("Logger installation failed: {}", e);
+        if let Err(e) = self.try_set_logger() {
+            platform::write(ConsoleMethod::Error, &e.to_string());
         }
     }
 
-    #[cfg(not(feature = "env_logger_string"))]
-    /// Set the logger instance as the main logger
-    pub fn set_logger(self) {
-        let result = set_logger(&WORKER_LOGGER);
-        if let Err(e) = result {
-            console_error!("Logger installation failed: {}", e);
+    fn read_filter(&self) -> RwLockReadGuard<'_, Filter> {
+        self.filter.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read_directives(&self) -> String {
+        self.directives
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update_filter(&self, init_string: &str) {
+        // `init_once` is called on every request, only warn when the directives change
+        if self.read_directives() == init_string {
+            return;
+        }
+        let filter = filter::parse(init_string);
+        let mut current = self.filter.write().unwrap_or_else(|e| e.into_inner());
+        // Only the installed logger owns the global maximum level
+        if self.is_installed() {
+            set_max_level(self.max_level(&filter));
         }
+        *current = filter;
+        *self.directives.write().unwrap_or_else(|e| e.into_inner()) = init_string.to_string();
     }
-}
 
-impl log::Log for Logger {
-    #[cfg(feature = "env_logger_string")]
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        self.filter.enabled(metadata)
+    fn is_installed(&self) -> bool {
+        INSTALLED
+            .get()
+            .is_some_and(|logger| std::ptr::eq(*logger, self))
+    }
+
+    /// The most verbose level records need to be logged at to reach the logger
+    fn max_level(&self, filter: &Filter) -> LevelFilter {
+        let retained = self
+            .ring_buffer
+            .map_or(LevelFilter::Off, |ring| ring.level());
+        filter.filter().max(retained)
     }
 
-    #[cfg(not(feature = "env_logger_string"))]
+    /// Write an entry to the console and every sink
+    fn emit(&self, entry: &Entry) {
+        let formatted = match self.format {
+            OutputFormat::Text => self.template.render(entry, &self.options),
+            OutputFormat::Json | OutputFormat::Object => entry.to_json(),
+        };
+        if let Some(console) = &self.console {
+            self.write_console(console, entry, &formatted);
+        }
+        for sink in &self.sinks {
+            sink.write(entry, &formatted);
+        }
+        #[cfg(any(test, feature = "testing"))]
+        testing::record(entry);
+    }
+
+    /// Write an entry to the console, styled with CSS if configured
+    #[cfg(target_arch = "wasm32")]
+    fn write_console(&self, console: &ConsoleSink, entry: &Entry, formatted: &str) {
+        match (self.format, self.options.style) {
+            (OutputFormat::Text, Style::Css) => {
+                let (text, css) = self.template.render_css(entry, &self.options);
+                console.write_styled(entry, &text, &css);
+            }
+            _ => console.write(entry, formatted),
+        }
+    }
+
+    /// Write an entry to the console, there is nothing to interpret CSS outside of `wasm32`
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write_console(&self, console: &ConsoleSink, entry: &Entry, formatted: &str) {
+        console.write(entry, formatted);
+    }
+
+    fn entry(&self, record: &Record) -> Entry {
+        let timestamp = (self.options.time != TimestampFormat::None).then(platform::now_millis);
+        let context = context::current_fields();
+        let mut entry = Entry::new(record, timestamp, context, self.options.location);
+        if let Some(redaction) = &self.redaction {
+            redaction.redact_entry(&mut entry);
+        }
+        entry
+    }
+
+    /// Render the console group label of a `tracing` span, redacting and escaping its fields like
+    /// those of records
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span_label(&self, name: &str, mut fields: format::Fields) -> String {
+        if let Some(redaction) = &self.redaction {
+            redaction.redact_fields(&mut fields);
+        }
+        let mut label = name.to_string();
+        format::write_fields(&mut label, &fields);
+        if self.options.escape {
+            label = format::escape_control(&label).into_owned();
+        }
+        label
+    }
+}
+
+impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        let filter = self.read_filter();
+        filter.enabled(metadata)
+            || context::overrides(metadata.level())
+            || self.ring_buffer.is_some_and(|ring| {
+                context::is_active() && filter.enabled_up_to(metadata, ring.level())
+            })
     }
 
     fn log(&self, record: &Record) {
-        #[cfg(feature = "env_logger_string")]
-        if !self.filter.matches(record) {
+        if !self.read_filter().matches(record) && !context::overrides(record.level()) {
+            // Only build entries that end up retained, records outside of a request are dropped
+            let retained = self.ring_buffer.filter(|ring| {
+                let filter = self.read_filter();
+                context::is_active()
+                    && filter.enabled_up_to(record.metadata(), ring.level())
+                    && filter.matches_message(record)
+            });
+            if let Some(ring) = retained {
+                if let Some(entry) = context::retain(self.entry(record), &ring) {
+                    self.emit(&entry);
+                }
+            }
             return;
         }
-        #[cfg(not(feature = "env_logger_string"))]
-        if !self.enabled(record.metadata()) {
-            return;
+        if record.level() == Level::Error {
+            for entry in context::take_trail() {
+                self.emit(&entry);
+            }
         }
-        let target = match (record.file(), record.line()) {
-            (Some(file), Some(line)) => format!("{}:{}", file, line),
-            _ => record.target().to_string(),
-        };
-        let level = record.level().to_string();
-        #[cfg(feature = "color")]
-        let level = match record.level() {
-            Level::Error => level.red(),
-            Level::Warn => level.yellow(),
-            Level::Info => level.cyan(),
-            Level::Debug => level.purple(),
-            _ => level.normal(),
-        };
-        let prompt = format!(
-            "[{time} {level} {target}]",
-            time = Date::now().to_string(),
-            level = level,
-            target = target,
-        );
-        #[cfg(feature = "color")]
-        let prompt = prompt.bold();
-        match record.level() {
-            Level::Debug => console_debug!("{} {}", prompt, record.args()),
-            Level::Error => console_error!("{} {}", prompt, record.args()),
-            Level::Warn => console_warn!("{} {}", prompt, record.args()),
-            _ => console_log!("{} {}", prompt, record.args()),
+        self.emit(&self.entry(record));
+    }
+
+    fn flush(&self) {
+        for sink in &self.sinks {
+            sink.flush();
         }
     }
+}
 
-    fn flush(&self) {}
+/// Let records down to `level` reach the installed logger, for a request context overriding its
+/// filter. The maximum level stays raised until the filter is updated.
+pub(crate) fn raise_max_level(level: LevelFilter) {
+    if INSTALLED.get().is_some() && level > log::max_level() {
+        set_max_level(level);
+    }
+}
+
+/// Ship the records buffered by the `HttpSink` of the installed logger
+///
+/// The buffer is drained when this function is called, the returned future only performs the
+/// request. It is meant to be passed to `Context::wait_until`, so that shipping doesn't delay the
+/// response.
+pub fn flush() -> impl Future<Output = Result<(), Error>> {
+    let batch = INSTALLED
+        .get()
+        .and_then(|logger| logger.http_sink.as_ref())
+        .and_then(|sink| sink.take_batch().map(|body| (sink, body)));
+    async move {
+        match batch {
+            Some((sink, body)) => sink.send(body).await.map_err(Error::Flush),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Get a handle changing the filter of the installed logger at runtime, `None` if no logger was
+/// installed by this crate
+pub fn handle() -> Option<Handle> {
+    INSTALLED.get().map(|logger| Handle::new(logger))
 }
 
 /// Initialize and install a logger with a string
@@ -161,17 +567,307 @@ pub fn init_with_level(level: &Level) {
     Logger::new(level.as_str()).set_logger();
 }
 
-/// Initialize and install a logger with a Cloudflare Workers environment variable
+/// Initialize and install a logger with a Cloudflare Workers environment variable. Problems in
+/// the filter string are written to the console as warnings.
 pub fn init_with_env<S: AsRef<str>>(env: &WorkerEnv, env_name: S) -> Result<(), WorkerError> {
     Logger::new(env.var(env_name.as_ref())?.to_string()).set_logger();
     Ok(())
 }
 
+/// Initialize and install a logger with a string, returning an error on failure
+pub fn try_init_with_string<S: AsRef<str>>(init_string: S) -> Result<(), Error> {
+    Logger::new(init_string).try_set_logger()
+}
+
+/// Initialize and install a logger with a `log::Level`, returning an error on failure
+pub fn try_init_with_level(level: &Level) -> Result<(), Error> {
+    Logger::new(level.as_str()).try_set_logger()
+}
+
+/// Initialize and install a logger with a Cloudflare Workers environment variable, returning an
+/// error on failure, including when the filter string has invalid directives
+pub fn try_init_with_env<S: AsRef<str>>(env: &WorkerEnv, env_name: S) -> Result<(), Error> {
+    Logger::new(read_filter_var(env, env_name.as_ref())?).try_set_logger()
+}
+
+/// Install a logger with a string on the first call. Later calls only update the filter of the
+/// installed logger, so this is safe to call at the start of every request.
+pub fn init_once<S: AsRef<str>>(init_string: S) -> Result<(), Error> {
+    match INSTALLED.get() {
+        Some(logger) => {
+            logger.update_filter(init_string.as_ref());
+            Ok(())
+        }
+        None => Logger::new(init_string).try_set_logger(),
+    }
+}
+
+/// Install a logger with a Cloudflare Workers environment variable on the first call. Later calls
+/// only update the filter of the installed logger, so this is safe to call inside
+/// `#[event(fetch)]` even when the isolate is reused across requests.
+///
+/// Returns an error without changing the filter if the variable has invalid directives.
+pub fn init_once_with_env<S: AsRef<str>>(env: &WorkerEnv, env_name: S) -> Result<(), Error> {
+    init_once(read_filter_var(env, env_name.as_ref())?)
+}
+
+/// Read a filter string from an environment variable, rejecting invalid directives
+fn read_filter_var(env: &WorkerEnv, env_name: &str) -> Result<String, Error> {
+    let init_string = env.var(env_name)?.to_string();
+    check_filter(init_string)
+}
+
+fn check_filter(init_string: String) -> Result<String, Error> {
+    let issues = parse_filter(&init_string);
+    if issues.iter().any(FilterIssue::is_error) {
+        return Err(Error::Filter(issues));
+    }
+    Ok(init_string)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use log::{LevelFilter, Log};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemorySink(Mutex<Vec<(Entry, String)>>);
+
+    impl Sink for MemorySink {
+        fn write(&self, entry: &Entry, formatted: &str) {
+            let entry = (entry.clone(), formatted.to_string());
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn sinks_receive_filtered_entries() {
+        let sink = Arc::new(MemorySink::default());
+        let logger = LoggerBuilder::new()
+            .filter("info")
+            .timestamp(false)
+            .source_location(false)
+            .format(OutputFormat::Json)
+            .console(false)
+            .sink(sink.clone())
+            .build();
+        let _guard = RequestContext::new().with_field("request_id", "r1").enter();
+        for level in [Level::Debug, Level::Warn] {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("hello"))
+                    .level(level)
+                    .target("app")
+                    .build(),
+            );
+        }
+        let entries = sink.0.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        let (entry, formatted) = &entries[0];
+        assert_eq!(entry.level, Level::Warn);
+        assert_eq!(entry.message, "hello");
+        assert_eq!(entry.context, vec![("request_id".to_string(), "r1".into())]);
+        assert_eq!(formatted, &entry.to_json());
+    }
+
+    #[test]
+    fn ring_buffer_is_written_out_on_error() {
+        let sink = Arc::new(MemorySink::default());
+        let logger = LoggerBuilder::new()
+            .filter("info,noisy=off")
+            .console(false)
+            .sink(sink.clone())
+            .ring_buffer(RingBuffer::new(Level::Debug))
+            .build();
+        let log_to = |target, level, message: &str| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .target(target)
+                    .build(),
+            )
+        };
+        let log = |level, message: &str| log_to("app", level, message);
+        let messages = || -> Vec<String> {
+            let entries = sink.0.lock().unwrap();
+            entries
+                .iter()
+                .map(|(entry, _)| entry.message.clone())
+                .collect()
+        };
+        {
+            let _guard = RequestContext::new().enter();
+            log(Level::Debug, "discarded");
+            log(Level::Trace, "below the ring buffer");
+        }
+        log(Level::Debug, "outside of a request");
+        let _guard = RequestContext::new().enter();
+        log(Level::Debug, "cache miss");
+        log(Level::Info, "request started");
+        log_to("noisy", Level::Debug, "turned off");
+        log_to("noisy", Level::Error, "still turned off");
+        log(Level::Error, "upstream failed");
+        log(Level::Debug, "after the failure");
+        assert_eq!(
+            messages(),
+            [
+                "request started",
+                "cache miss",
+                "upstream failed",
+                "after the failure"
+            ]
+        );
+    }
+
+    #[test]
+    fn context_level_overrides_filter() {
+        let sink = Arc::new(MemorySink::default());
+        let logger = LoggerBuilder::new()
+            .filter("error")
+            .console(false)
+            .sink(sink.clone())
+            .build();
+        let log = || {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("slow upstream"))
+                    .level(Level::Warn)
+                    .build(),
+            )
+        };
+        log();
+        {
+            let _guard = RequestContext::new().with_level(LevelFilter::Warn).enter();
+            log();
+        }
+        log();
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert_eq!(
+            check_filter("info,app=debug".to_string()).unwrap(),
+            "info,app=debug"
+        );
+        let error = check_filter("info,app=verbose".to_string()).unwrap_err();
+        assert!(matches!(&error, Error::Filter(issues) if issues.len() == 1));
+        assert!(error
+            .to_string()
+            .starts_with("invalid log filter: unknown level"));
+        // A misspelled level reads as a target, leaving no default level
+        let error = check_filter("debg".to_string()).unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::Filter(issues) if issues[0].kind == FilterIssueKind::UnknownDefault
+        ));
+        // Warnings alone don't reject the filter
+        assert!(check_filter("debug,debug".to_string()).is_ok());
+        assert!(check_filter("app,db=debug".to_string()).is_ok());
+    }
+
+    #[test]
+    fn host_fallback_writes_to_stderr() {
+        let logger = LoggerBuilder::new()
+            .filter("trace")
+            .format(OutputFormat::Object)
+            .build();
+        let _guard = RequestContext::new().with_field("path", "/").enter();
+        let lines = platform::capture_stderr(|| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("written to stderr"))
+                    .level(Level::Trace)
+                    .build(),
+            );
+            logger.flush();
+        });
+        assert_eq!(lines.len(), 1);
+        let object: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(object["message"], "written to stderr");
+        assert_eq!(object["level"], "TRACE");
+        assert_eq!(object["context"]["path"], "/");
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn span_labels_are_redacted_and_escaped() {
+        let logger = LoggerBuilder::new()
+            .redaction(Redaction::empty().deny_key("token"))
+            .escape_control(true)
+            .build();
+        let fields = vec![
+            ("user".to_string(), "ada\u{1b}[2J\u{202e}x".into()),
+            ("token".to_string(), "abc".into()),
+        ];
+        assert_eq!(
+            logger.span_label("request", fields),
+            "request user=ada\\x1b[2J\\u{202e}x token=[REDACTED]"
+        );
+    }
+
+    #[test]
+    fn unchanged_filters_are_not_reparsed() {
+        let logger = Logger::new("app,db=debug");
+        let lines = platform::capture_stderr(|| {
+            logger.update_filter("app,db=debug");
+            logger.update_filter("api,db=debug");
+            logger.update_filter("api,db=debug");
+        });
+        assert_eq!(lines.len(), 1);
+        assert_eq!(logger.read_directives(), "api,db=debug");
+    }
+
+    #[test]
+    fn building_writes_nothing() {
+        let lines = platform::capture_stderr(|| {
+            LoggerBuilder::new().filter("info,app=verbose").build();
+        });
+        assert_eq!(lines, Vec::<String>::new());
+    }
+
+    #[test]
+    fn init_once_is_idempotent() {
+        super::init_once("warn").unwrap();
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+        super::init_once("debug").unwrap();
+        assert_eq!(log::max_level(), LevelFilter::Debug);
+        assert!(matches!(
+            super::try_init_with_string("info"),
+            Err(super::Error::SetLogger(_))
+        ));
+    }
+
+    #[test]
+    fn failed_installs_free_the_logger() {
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Sink for DropCounter {
+            fn write(&self, _entry: &Entry, _formatted: &str) {}
+        }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        // Make sure a logger is installed
+        let _ = Logger::new("trace").try_set_logger();
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let logger = LoggerBuilder::new()
+            .sink(DropCounter(dropped.clone()))
+            .build();
+        assert!(logger.try_set_logger().is_err());
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    }
 }
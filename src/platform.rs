@@ -0,0 +1,142 @@
+//! Platform specific primitives.
+//!
+//! On `wasm32` these are backed by the JavaScript runtime. Everywhere else they fall back to the
+//! standard library, so that code using the logger can be tested with a plain `cargo test`.
+
+use crate::console::ConsoleMethod;
+
+#[cfg(target_arch = "wasm32")]
+use worker::js_sys::{Date as JsDate, Math};
+
+#[cfg(target_arch = "wasm32")]
+use worker::wasm_bindgen::JsValue;
+
+#[cfg(all(feature = "tracing", target_arch = "wasm32"))]
+use worker::worker_sys::web_sys::console;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::hash_map::RandomState;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::hash::{BuildHasher, Hasher};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+use std::cell::RefCell;
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+thread_local! {
+    /// Lines written by the current thread while captured by [`capture_stderr`]
+    static STDERR: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Current time in milliseconds since the Unix epoch
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_millis() -> u64 {
+    JsDate::now() as u64
+}
+
+/// Current time in milliseconds since the Unix epoch
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A random number, not suitable for cryptographic use
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn random_u32() -> u32 {
+    (Math::random() * f64::from(u32::MAX)) as u32
+}
+
+/// A random number, not suitable for cryptographic use
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn random_u32() -> u32 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u64(now_millis());
+    hasher.finish() as u32
+}
+
+/// Format a timestamp like JavaScript's `Date.prototype.toString`
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn date_string(millis: u64) -> String {
+    JsDate::new(&JsValue::from_f64(millis as f64))
+        .to_string()
+        .into()
+}
+
+/// Format a timestamp for text output, there is no local time zone to speak of outside of
+/// JavaScript so this is an ISO 8601 date in UTC
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn date_string(millis: u64) -> String {
+    crate::format::iso8601(millis)
+}
+
+/// Write a message with a console method
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn write(method: ConsoleMethod, message: &str) {
+    method.call(&JsValue::from_str(message));
+}
+
+/// Write a message to stderr, standing in for the console
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn write(_method: ConsoleMethod, message: &str) {
+    write_stderr(message);
+}
+
+/// Write a line to stderr, or to the lines captured by [`capture_stderr`] in tests
+#[cfg(not(target_arch = "wasm32"))]
+fn write_stderr(line: &str) {
+    #[cfg(test)]
+    {
+        let captured = STDERR.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(lines) => {
+                lines.push(line.to_string());
+                true
+            }
+            None => false,
+        });
+        if captured {
+            return;
+        }
+    }
+    eprintln!("{}", line);
+}
+
+/// Run a function, returning the lines it wrote to stderr on this thread instead of writing them
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn capture_stderr<F: FnOnce()>(f: F) -> Vec<String> {
+    STDERR.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    f();
+    STDERR.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
+
+/// Open a collapsible console group
+#[cfg(all(feature = "tracing", target_arch = "wasm32"))]
+pub(crate) fn group(label: &str) {
+    console::group_1(&JsValue::from_str(label));
+}
+
+/// Write the label of a console group to stderr
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+pub(crate) fn group(label: &str) {
+    write_stderr(label);
+}
+
+/// Close the innermost console group
+#[cfg(all(feature = "tracing", target_arch = "wasm32"))]
+pub(crate) fn group_end() {
+    console::group_end();
+}
+
+/// Console groups have no equivalent on stderr
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+pub(crate) fn group_end() {}
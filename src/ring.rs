@@ -0,0 +1,142 @@
+//! Retention of records below the active level until a request fails.
+
+use log::{Level, LevelFilter};
+
+use crate::sink::Entry;
+
+use std::collections::VecDeque;
+
+/// Default maximum number of records retained per request
+const DEFAULT_MAX_RECORDS: usize = 256;
+
+/// Default maximum size of the records retained per request
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// Keeps records that are below the active level but at or above `level` in memory for the
+/// duration of a request
+///
+/// The retained records are written out, in order, before the first `Error` record of the request
+/// or when the request is marked failed with [`context::mark_failed`](crate::context::mark_failed).
+/// They are discarded when the request ends otherwise. Records are only retained while a
+/// [`RequestContext`](crate::RequestContext) is active.
+///
+/// ```rust
+/// use log::Level;
+/// use worker_logger::{LoggerBuilder, RingBuffer};
+///
+/// let logger = LoggerBuilder::new()
+///     .filter("info")
+///     .ring_buffer(RingBuffer::new(Level::Debug).max_records(100))
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RingBuffer {
+    level: LevelFilter,
+    max_records: usize,
+    max_bytes: usize,
+}
+
+impl RingBuffer {
+    /// Retain records down to the given level
+    pub fn new(level: Level) -> Self {
+        RingBuffer {
+            level: level.to_level_filter(),
+            max_records: DEFAULT_MAX_RECORDS,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Set the maximum number of records retained per request, the oldest records are dropped once
+    /// it is exceeded. Defaults to 256.
+    pub fn max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    /// Set the maximum size in bytes of the records retained per request, estimated from their
+    /// messages and fields. The oldest records are dropped once it is exceeded. Defaults to 64 KiB.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// The most verbose level retained
+    pub(crate) fn level(&self) -> LevelFilter {
+        self.level
+    }
+}
+
+/// Records retained for a request
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Trail {
+    entries: VecDeque<(Entry, usize)>,
+    bytes: usize,
+    /// Set once the request failed, from then on records are written out instead of retained
+    pub(crate) failed: bool,
+}
+
+impl Trail {
+    /// Retain an entry, dropping the oldest entries to stay within the limits
+    pub(crate) fn push(&mut self, entry: Entry, limits: &RingBuffer) {
+        let size = size(&entry);
+        if limits.max_records == 0 || size > limits.max_bytes {
+            return;
+        }
+        while self.entries.len() >= limits.max_records || self.bytes + size > limits.max_bytes {
+            match self.entries.pop_front() {
+                Some((_, dropped)) => self.bytes -= dropped,
+                None => break,
+            }
+        }
+        self.bytes += size;
+        self.entries.push_back((entry, size));
+    }
+
+    /// Take the retained entries, in the order they were logged
+    pub(crate) fn take(&mut self) -> Vec<Entry> {
+        self.bytes = 0;
+        self.entries.drain(..).map(|(entry, _)| entry).collect()
+    }
+}
+
+/// Estimate the memory held by an entry
+fn size(entry: &Entry) -> usize {
+    let fields = entry
+        .fields
+        .iter()
+        .map(|(key, value)| key.len() + value.to_string().len())
+        .sum::<usize>();
+    entry.target.len() + entry.message.len() + fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Record;
+
+    fn entry(message: &str) -> Entry {
+        Entry::new(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(Level::Debug)
+                .target("app")
+                .build(),
+            None,
+            Vec::new(),
+            false,
+        )
+    }
+
+    #[test]
+    fn trail_is_bounded() {
+        let limits = RingBuffer::new(Level::Debug).max_records(2).max_bytes(12);
+        let mut trail = Trail::default();
+        trail.push(entry("one"), &limits);
+        trail.push(entry("two"), &limits);
+        trail.push(entry("three"), &limits);
+        let messages: Vec<_> = trail.take().into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, ["three"]);
+        trail.push(entry("far too long to be retained"), &limits);
+        assert!(trail.take().is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+//! Logging integration for `worker::Router`.
+
+use matchit::Router as Patterns;
+use serde_json::{Map, Value};
+use worker::{Env, Method, Request, Response, Result, RouteContext, Router};
+
+use crate::access::AccessLog;
+use crate::context::RequestContext;
+
+use std::collections::HashMap;
+use std::future::Future;
+
+type ContextFn<'a> = Box<dyn Fn(&Request) -> RequestContext + 'a>;
+
+/// A `worker::Router` running every route with a [`RequestContext`] and an [`AccessLog`]
+///
+/// Routes are registered like on `Router`. When a request is run, the context of the request holds
+/// the `route` pattern it matched, e.g. `/users/:id`, and the matched `params` as an object, e.g.
+/// `{"id": "42"}`, in addition to the fields of [`RequestContext::from_request`]. Every record
+/// logged by the route handler carries them, and so does the access record written once the
+/// handler completes.
+///
+/// ```rust,ignore
+/// use worker::*;
+/// use worker_logger::LoggedRouter;
+///
+/// #[event(fetch)]
+/// async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+///     LoggedRouter::new()
+///         .get_async("/users/:id", |_req, ctx| async move {
+///             log::info!("looking up user");
+///             Response::ok(ctx.param("id").unwrap())
+///         })
+///         .run(req, env)
+///         .await
+/// }
+/// ```
+pub struct LoggedRouter<'a, D> {
+    router: Router<'a, D>,
+    patterns: HashMap<Method, Patterns<String>>,
+    any_method: Patterns<String>,
+    context: ContextFn<'a>,
+    access_log: Option<AccessLog>,
+}
+
+impl<'a> LoggedRouter<'a, ()> {
+    /// Create a router without data
+    pub fn new() -> Self {
+        Self::with_data(())
+    }
+}
+
+impl Default for LoggedRouter<'_, ()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! routes {
+    ($($(#[$doc:meta])* $name:ident, $name_async:ident => $method:expr;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name(
+                mut self,
+                pattern: &str,
+                func: fn(Request, RouteContext<D>) -> Result<Response>,
+            ) -> Self {
+                self.router = self.router.$name(pattern, func);
+                self.insert(pattern, $method);
+                self
+            }
+
+            $(#[$doc])*
+            ///
+            /// Enables the use of `async/await` syntax in the callback.
+            pub fn $name_async<T>(
+                mut self,
+                pattern: &str,
+                func: fn(Request, RouteContext<D>) -> T,
+            ) -> Self
+            where
+                T: Future<Output = Result<Response>> + 'a,
+            {
+                self.router = self.router.$name_async(pattern, func);
+                self.insert(pattern, $method);
+                self
+            }
+        )*
+    };
+}
+
+impl<'a, D: 'a> LoggedRouter<'a, D> {
+    /// Create a router with data available to the routes, like `Router::with_data`
+    pub fn with_data(data: D) -> Self {
+        LoggedRouter {
+            router: Router::with_data(data),
+            patterns: HashMap::new(),
+            any_method: Patterns::new(),
+            context: Box::new(RequestContext::from_request),
+            access_log: Some(AccessLog::new()),
+        }
+    }
+
+    /// Create the context of each request with a function instead of
+    /// [`RequestContext::from_request`], e.g. to apply a `DebugOverride`. The route fields are
+    /// added to the context it returns.
+    pub fn context<F: Fn(&Request) -> RequestContext + 'a>(mut self, context: F) -> Self {
+        self.context = Box::new(context);
+        self
+    }
+
+    /// Set the access log written once a route completes, or disable it with `None`
+    pub fn access_log(mut self, access_log: Option<AccessLog>) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    routes! {
+        /// Register a handler responding to HEAD requests, see `Router::head`.
+        head, head_async => Some(vec![Method::Head]);
+        /// Register a handler responding to GET requests, see `Router::get`.
+        get, get_async => Some(vec![Method::Get]);
+        /// Register a handler responding to POST requests, see `Router::post`.
+        post, post_async => Some(vec![Method::Post]);
+        /// Register a handler responding to PUT requests, see `Router::put`.
+        put, put_async => Some(vec![Method::Put]);
+        /// Register a handler responding to PATCH requests, see `Router::patch`.
+        patch, patch_async => Some(vec![Method::Patch]);
+        /// Register a handler responding to DELETE requests, see `Router::delete`.
+        delete, delete_async => Some(vec![Method::Delete]);
+        /// Register a handler responding to OPTIONS requests, see `Router::options`.
+        options, options_async => Some(vec![Method::Options]);
+        /// Register a handler responding to requests of any method, see `Router::on`.
+        on, on_async => Some(Method::all());
+        /// Register a handler responding to requests of any method no other route matched, see
+        /// `Router::or_else_any_method`.
+        or_else_any_method, or_else_any_method_async => None;
+    }
+
+    /// Run the route matching a request with its context active, then write the access record
+    pub async fn run(self, req: Request, env: Env) -> Result<Response> {
+        let mut context = (self.context)(&req);
+        if let Some((route, params)) = self.route(&req.method(), &req.path()) {
+            context.insert("route", route);
+            context.insert("params", params);
+        }
+        let router = self.router;
+        match self.access_log {
+            Some(access_log) => {
+                let run = access_log.serve(req, |req| router.run(req, env));
+                context.scope(run).await
+            }
+            None => context.scope(router.run(req, env)).await,
+        }
+    }
+
+    /// Record the pattern of a route for the given methods, or for any method no other route
+    /// matched if `None`
+    fn insert(&mut self, pattern: &str, methods: Option<Vec<Method>>) {
+        // `Router` panics on conflicting patterns before they get here
+        match methods {
+            Some(methods) => {
+                for method in methods {
+                    let patterns = self.patterns.entry(method).or_default();
+                    let _ = patterns.insert(pattern, pattern.to_string());
+                }
+            }
+            None => {
+                let _ = self.any_method.insert(pattern, pattern.to_string());
+            }
+        }
+    }
+
+    /// Find the pattern matching a request and its parameters, the same way `Router` does
+    fn route(&self, method: &Method, path: &str) -> Option<(String, Value)> {
+        let matches = |method| {
+            self.patterns
+                .get(method)
+                .and_then(|patterns: &Patterns<String>| patterns.at(path).ok())
+        };
+        let matched = match matches(method) {
+            Some(matched) => matched,
+            // Answered with 405 Method Not Allowed
+            None if Method::all()
+                .iter()
+                .filter(|other| !matches!(other, Method::Head | Method::Options | Method::Trace))
+                .any(|other| matches(other).is_some()) =>
+            {
+                return None
+            }
+            None => self.any_method.at(path).ok()?,
+        };
+        let params: Map<String, Value> = matched
+            .params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.into()))
+            .collect();
+        Some((matched.value.clone(), params.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ok(_req: Request, _ctx: RouteContext<()>) -> Result<Response> {
+        Response::empty()
+    }
+
+    #[test]
+    fn requests_are_matched_to_patterns() {
+        let router = LoggedRouter::new()
+            .get("/users/:id", ok)
+            .post("/users", ok)
+            .or_else_any_method("/*path", ok);
+        assert_eq!(
+            router.route(&Method::Get, "/users/42"),
+            Some(("/users/:id".to_string(), json!({"id": "42"})))
+        );
+        assert_eq!(
+            router.route(&Method::Post, "/users"),
+            Some(("/users".to_string(), json!({})))
+        );
+        assert_eq!(
+            router.route(&Method::Delete, "/teams/42"),
+            Some(("/*path".to_string(), json!({"path": "teams/42"})))
+        );
+        assert_eq!(router.route(&Method::Delete, "/users/42"), None);
+        let router = LoggedRouter::new().get("/users/:id", ok);
+        assert_eq!(router.route(&Method::Get, "/teams/1"), None);
+    }
+}
@@ -0,0 +1,25 @@
+//! Keyed hashing shared by debug overrides and redaction.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use std::fmt::Write;
+
+pub(crate) type HmacSha256 = Hmac<Sha256>;
+
+/// Start an HMAC-SHA256 of a message keyed with `key`
+pub(crate) fn mac(key: &[u8], message: &str) -> HmacSha256 {
+    // HMAC accepts keys of any length
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    mac
+}
+
+/// Encode bytes as lowercase hex
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
@@ -0,0 +1,102 @@
+//! Destinations records are written to.
+
+use log::{Level, Record};
+use serde_json::Value;
+
+use crate::format::{self, Fields};
+
+use std::sync::Arc;
+
+/// A record prepared for output, passed to every [`Sink`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Entry {
+    /// Time the record was logged in milliseconds since the Unix epoch, `None` if timestamps are
+    /// disabled
+    pub timestamp: Option<u64>,
+    /// Level of the record
+    pub level: Level,
+    /// Target of the record
+    pub target: String,
+    /// Module the record was logged in
+    pub module_path: Option<String>,
+    /// Source file the record was logged in, `None` if source locations are disabled
+    pub file: Option<String>,
+    /// Source line the record was logged at, `None` if source locations are disabled
+    pub line: Option<u32>,
+    /// The formatted message
+    pub message: String,
+    /// Key-values attached to the record
+    pub fields: Vec<(String, Value)>,
+    /// Fields of the request context active when the record was logged
+    pub context: Vec<(String, Value)>,
+}
+
+impl Entry {
+    pub(crate) fn new(
+        record: &Record,
+        timestamp: Option<u64>,
+        context: Fields,
+        location: bool,
+    ) -> Self {
+        Entry {
+            timestamp,
+            level: record.level(),
+            target: record.target().to_string(),
+            module_path: record.module_path().map(str::to_string),
+            file: record.file().filter(|_| location).map(str::to_string),
+            line: record.line().filter(|_| location),
+            message: record.args().to_string(),
+            fields: format::fields(record),
+            context,
+        }
+    }
+
+    /// Get the value of a key-value attached to the record
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Get the value of a field of the request context
+    pub fn context_field(&self, key: &str) -> Option<&Value> {
+        self.context.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Render the entry as a single line JSON object
+    pub fn to_json(&self) -> String {
+        format::json(self)
+    }
+}
+
+/// A destination for log records
+///
+/// The JavaScript console is the default sink, more can be added with
+/// [`LoggerBuilder::sink`](crate::LoggerBuilder::sink).
+pub trait Sink: Send + Sync {
+    /// Write an entry. `formatted` is the entry rendered in the logger's output format, with JSON
+    /// standing in for [`OutputFormat::Object`](crate::OutputFormat::Object).
+    fn write(&self, entry: &Entry, formatted: &str);
+
+    /// Flush buffered entries, if any
+    fn flush(&self) {}
+}
+
+impl<S: Sink + ?Sized> Sink for Arc<S> {
+    fn write(&self, entry: &Entry, formatted: &str) {
+        (**self).write(entry, formatted);
+    }
+
+    fn flush(&self) {
+        (**self).flush();
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn write(&self, entry: &Entry, formatted: &str) {
+        (**self).write(entry, formatted);
+    }
+
+    fn flush(&self) {
+        (**self).flush();
+    }
+}
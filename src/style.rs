@@ -0,0 +1,106 @@
+//! Styling of text output for terminals and browser devtools.
+
+use log::Level;
+
+/// How text output is styled
+///
+/// The level is colored and everything before the message is written in bold, see
+/// [`Template`](crate::Template).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Style {
+    /// No styling
+    Plain,
+    /// ANSI escape codes, for terminals such as the output of `wrangler dev`
+    #[cfg(feature = "color")]
+    Ansi,
+    /// `%c` directives with CSS arguments passed to the console, for the Chrome DevTools inspector
+    /// attached to `wrangler dev`. Outside of `wasm32` this is the same as [`Style::Plain`].
+    Css,
+    /// [`Style::Ansi`] if stderr is a terminal and [`Style::Plain`] otherwise. A Worker can't tell
+    /// whether an inspector is attached, so on `wasm32` this is plain unless a style is picked at
+    /// runtime with [`Style::from_env`].
+    Auto,
+}
+
+impl Default for Style {
+    /// [`Style::Ansi`] with the `color` feature, [`Style::Plain`] otherwise
+    fn default() -> Self {
+        #[cfg(feature = "color")]
+        return Style::Ansi;
+        #[cfg(not(feature = "color"))]
+        return Style::Plain;
+    }
+}
+
+impl Style {
+    /// Read the style from a Worker environment variable holding `plain`, `ansi`, `css` or `auto`,
+    /// ignoring case, e.g. `LOG_STYLE = "css"` in the vars of `wrangler dev`. A missing variable or
+    /// an unknown value selects [`Style::Auto`], so deployed Workers stay unstyled unless the
+    /// variable is set for them.
+    ///
+    /// ```rust,ignore
+    /// LoggerBuilder::new()
+    ///     .style(Style::from_env(&env, "LOG_STYLE"))
+    ///     .install()?;
+    /// ```
+    pub fn from_env<S: AsRef<str>>(env: &worker::Env, var_name: S) -> Style {
+        env.var(var_name.as_ref())
+            .ok()
+            .and_then(|value| Style::from_name(&value.to_string()))
+            .unwrap_or(Style::Auto)
+    }
+
+    /// Parse the name of a style, [`Style::Ansi`] is plain without the `color` feature
+    pub(crate) fn from_name(name: &str) -> Option<Style> {
+        Some(match name.trim().to_ascii_lowercase().as_str() {
+            "plain" => Style::Plain,
+            #[cfg(feature = "color")]
+            "ansi" => Style::Ansi,
+            #[cfg(not(feature = "color"))]
+            "ansi" => Style::Plain,
+            "css" => Style::Css,
+            "auto" => Style::Auto,
+            _ => return None,
+        })
+    }
+
+    /// Pick the style [`Style::Auto`] stands for
+    pub(crate) fn resolve(self) -> Style {
+        match self {
+            Style::Auto if cfg!(target_arch = "wasm32") => Style::Plain,
+            #[cfg(feature = "color")]
+            Style::Auto if std::io::IsTerminal::is_terminal(&std::io::stderr()) => Style::Ansi,
+            Style::Auto => Style::Plain,
+            style => style,
+        }
+    }
+}
+
+/// CSS applied to the prompt, everything before the message
+pub(crate) const PROMPT_CSS: &str = "font-weight: bold";
+
+/// CSS coloring a level, matching the ANSI colors
+pub(crate) fn level_css(level: Level) -> &'static str {
+    match level {
+        Level::Error => "color: red",
+        Level::Warn => "color: darkorange",
+        Level::Info => "color: darkcyan",
+        Level::Debug => "color: purple",
+        Level::Trace => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_are_parsed_by_name() {
+        assert_eq!(Style::from_name("CSS "), Some(Style::Css));
+        assert_eq!(Style::from_name("plain"), Some(Style::Plain));
+        assert_eq!(Style::from_name("auto"), Some(Style::Auto));
+        assert_eq!(Style::from_name("ansi"), Some(Style::default()));
+        assert_eq!(Style::from_name("html"), None);
+    }
+}
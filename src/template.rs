@@ -0,0 +1,598 @@
+//! Templates describing the layout of text output.
+
+use crate::format::{self, Options, TimestampFormat};
+use crate::sink::Entry;
+use crate::style::{self, PROMPT_CSS};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "color")]
+use colored::Colorize;
+
+#[cfg(feature = "color")]
+use log::Level;
+
+#[cfg(feature = "color")]
+use crate::style::Style;
+
+/// The template used when none is configured, e.g. `[time level file:line] message key=value`
+const DEFAULT_TEMPLATE: &str = "[{?{time} }{level} {location}] {msg}{? {kv}}{? {context}}";
+
+/// The layout of text output, parsed once when the logger is configured
+///
+/// A template is literal text with placeholders in braces:
+///
+///  - `{time}`: the timestamp of the record, missing if timestamps are disabled. The format of the
+///    logger can be overridden with `{time:iso}`, `{time:epoch}`, `{time:request}`,
+///    `{time:isolate}` or `{time:date}`, see [`TimestampFormat`]
+///  - `{level}`: the level of the record
+///  - `{target}`: the target of the record
+///  - `{module}`: the module the record was logged in, if known
+///  - `{file}` and `{line}`: the source location of the record, missing if source locations are
+///    disabled
+///  - `{location}`: `file:line` if known, the target otherwise
+///  - `{msg}`: the message
+///  - `{kv}`: the key-values of the record as `key=value` pairs, missing if there are none
+///  - `{context}`: the fields of the request context as `key=value` pairs, missing if there are
+///    none
+///
+/// A placeholder can set a minimum width and an alignment after a colon, e.g. `{level:5}` or
+/// `{level:>5}`, using `<`, `>` or `^` like `format!`. Text is left-aligned by default.
+///
+/// `{?...}` is an optional section, only written if none of the placeholders inside it is missing.
+/// Braces are escaped by doubling them, `{{` and `}}`.
+///
+/// When styled, the level is colored and everything before `{msg}` is written in bold, see
+/// [`Style`].
+///
+/// ```rust
+/// use worker_logger::{LoggerBuilder, Template};
+///
+/// let template: Template = "{time} {level:5} {module}{?@{file}:{line}} {msg}{? {kv}}"
+///     .parse()
+///     .unwrap();
+/// let logger = LoggerBuilder::new().template(template).build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder, Option<Spec>),
+    Optional(Vec<Segment>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    /// The timestamp, in the format of the logger unless overridden
+    Time(Option<TimestampFormat>),
+    Level,
+    Target,
+    Module,
+    File,
+    Line,
+    Location,
+    Message,
+    Kv,
+    Context,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "time" => Placeholder::Time(None),
+            "level" => Placeholder::Level,
+            "target" => Placeholder::Target,
+            "module" => Placeholder::Module,
+            "file" => Placeholder::File,
+            "line" => Placeholder::Line,
+            "location" => Placeholder::Location,
+            "msg" => Placeholder::Message,
+            "kv" => Placeholder::Kv,
+            "context" => Placeholder::Context,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Spec {
+    align: Align,
+    width: usize,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Option<Self> {
+        let (align, width) = match spec.chars().next()? {
+            '<' => (Align::Left, &spec[1..]),
+            '>' => (Align::Right, &spec[1..]),
+            '^' => (Align::Center, &spec[1..]),
+            _ => (Align::Left, spec),
+        };
+        let width = width.parse().ok()?;
+        Some(Spec { align, width })
+    }
+
+    fn pad(&self, value: &str) -> String {
+        let padding = self.width.saturating_sub(value.chars().count());
+        let (before, after) = match self.align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        format!("{}{}{}", " ".repeat(before), value, " ".repeat(after))
+    }
+}
+
+/// An error in the syntax of a [`Template`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    position: usize,
+    message: String,
+}
+
+impl TemplateError {
+    fn new<M: Into<String>>(position: usize, message: M) -> Self {
+        TemplateError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Byte offset of the error in the template
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    /// Parse segments until the end of the template, or the `}` closing an optional section
+    fn segments(&mut self, nested: bool) -> Result<Vec<Segment>, TemplateError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let start = self.position;
+        loop {
+            match self.bump() {
+                None if nested => {
+                    return Err(TemplateError::new(start, "unclosed optional section"));
+                }
+                None => break,
+                Some('{') if self.peek() == Some('{') => {
+                    self.bump();
+                    literal.push('{');
+                }
+                Some('}') if self.peek() == Some('}') => {
+                    self.bump();
+                    literal.push('}');
+                }
+                Some('}') if nested => break,
+                Some('}') => {
+                    return Err(TemplateError::new(self.position - 1, "unmatched `}`"));
+                }
+                Some('{') => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    if self.peek() == Some('?') {
+                        self.bump();
+                        segments.push(Segment::Optional(self.segments(true)?));
+                    } else {
+                        segments.push(self.placeholder()?);
+                    }
+                }
+                Some(c) => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(segments)
+    }
+
+    fn placeholder(&mut self) -> Result<Segment, TemplateError> {
+        let start = self.position;
+        let end = self.source[start..]
+            .find(['}', '{'])
+            .map(|offset| start + offset)
+            .filter(|&end| self.source[end..].starts_with('}'))
+            .ok_or_else(|| TemplateError::new(start - 1, "unclosed placeholder"))?;
+        let contents = &self.source[start..end];
+        self.position = end + 1;
+        let (name, spec) = match contents.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (contents, None),
+        };
+        let placeholder = Placeholder::from_name(name.trim())
+            .ok_or_else(|| TemplateError::new(start, format!("unknown placeholder `{}`", name)))?;
+        let spec = spec.map(str::trim);
+        if let (Placeholder::Time(_), Some(time)) = (placeholder, spec) {
+            if let Some(format) = TimestampFormat::from_name(time) {
+                return Ok(Segment::Placeholder(Placeholder::Time(Some(format)), None));
+            }
+        }
+        let spec =
+            match spec {
+                Some(spec) => Some(Spec::parse(spec).ok_or_else(|| {
+                    TemplateError::new(start, format!("invalid format `{}`", spec))
+                })?),
+                None => None,
+            };
+        Ok(Segment::Placeholder(placeholder, spec))
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let segments = Parser {
+            source,
+            position: 0,
+        }
+        .segments(false)?;
+        Ok(Template { segments })
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE
+            .parse()
+            .expect("the default template is valid")
+    }
+}
+
+impl Template {
+    /// Render an entry, making everything before the message bold when styling with ANSI escape
+    /// codes. Other styles are rendered as plain text.
+    pub(crate) fn render(&self, entry: &Entry, options: &Options) -> String {
+        let mut out = Output::default();
+        let mut prompt = None;
+        for segment in &self.segments {
+            if let Segment::Placeholder(Placeholder::Message, _) = segment {
+                prompt = Some(out.text.len());
+            }
+            render_segment(&mut out, segment, entry, options, prompt.is_none());
+        }
+        let out = out.text;
+        #[cfg(feature = "color")]
+        let out = match prompt.filter(|_| options.style == Style::Ansi) {
+            Some(end) => {
+                let (prompt, message) = out.split_at(end);
+                let trimmed = prompt.trim_end();
+                let spacing = &prompt[trimmed.len()..];
+                format!("{}{}{}", trimmed.bold(), spacing, message)
+            }
+            None => out,
+        };
+        out
+    }
+
+    /// Render an entry as a format string with `%c` directives, along with the CSS arguments of
+    /// the directives
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn render_css(&self, entry: &Entry, options: &Options) -> (String, Vec<String>) {
+        let mut out = Output {
+            text: String::new(),
+            css: Some(Vec::new()),
+        };
+        out.style(PROMPT_CSS);
+        let mut prompt = true;
+        for segment in &self.segments {
+            if let Segment::Placeholder(Placeholder::Message, _) = segment {
+                out.style("");
+                prompt = false;
+            }
+            render_segment(&mut out, segment, entry, options, prompt);
+        }
+        (out.text, out.css.unwrap_or_default())
+    }
+}
+
+/// Rendered text, with the arguments of its `%c` directives when styling with CSS
+#[derive(Default)]
+struct Output {
+    text: String,
+    css: Option<Vec<String>>,
+}
+
+impl Output {
+    fn push(&mut self, text: &str) {
+        match self.css {
+            // `%` starts a directive in the format string
+            Some(_) => self.text.push_str(&text.replace('%', "%%")),
+            None => self.text.push_str(text),
+        }
+    }
+
+    /// Apply CSS to the text written afterwards, if styling with CSS
+    fn style(&mut self, css: &str) {
+        if let Some(args) = &mut self.css {
+            self.text.push_str("%c");
+            args.push(css.to_string());
+        }
+    }
+
+    fn section(&self) -> Output {
+        Output {
+            text: String::new(),
+            css: self.css.as_ref().map(|_| Vec::new()),
+        }
+    }
+
+    fn append(&mut self, section: Output) {
+        self.text.push_str(&section.text);
+        if let (Some(args), Some(section)) = (&mut self.css, section.css) {
+            args.extend(section);
+        }
+    }
+}
+
+/// Render a segment, returns `false` if a placeholder in it has no value
+fn render_segment(
+    out: &mut Output,
+    segment: &Segment,
+    entry: &Entry,
+    options: &Options,
+    prompt: bool,
+) -> bool {
+    match segment {
+        Segment::Literal(literal) => {
+            out.push(literal);
+            true
+        }
+        Segment::Placeholder(placeholder, spec) => match value(*placeholder, entry, options) {
+            Some(value) => {
+                let value = match spec {
+                    Some(spec) => spec.pad(&value),
+                    None => value,
+                };
+                match placeholder {
+                    Placeholder::Level => {
+                        let css = format!("{}; {}", style::level_css(entry.level), PROMPT_CSS);
+                        out.style(if prompt {
+                            &css
+                        } else {
+                            style::level_css(entry.level)
+                        });
+                        out.push(&colorize_level(value, entry, options));
+                        out.style(if prompt { PROMPT_CSS } else { "" });
+                    }
+                    _ => out.push(&value),
+                }
+                true
+            }
+            None => false,
+        },
+        Segment::Optional(segments) => {
+            let mut section = out.section();
+            let complete = segments
+                .iter()
+                .all(|segment| render_segment(&mut section, segment, entry, options, prompt));
+            if complete {
+                out.append(section);
+            }
+            true
+        }
+    }
+}
+
+fn value(placeholder: Placeholder, entry: &Entry, options: &Options) -> Option<String> {
+    match placeholder {
+        Placeholder::Time(format) => entry
+            .timestamp
+            .and_then(|millis| format.unwrap_or(options.time).render(millis, options.built)),
+        Placeholder::Level => Some(entry.level.to_string()),
+        Placeholder::Target => Some(entry.target.clone()),
+        Placeholder::Module => entry.module_path.clone(),
+        Placeholder::File => entry.file.clone(),
+        Placeholder::Line => entry.line.map(|line| line.to_string()),
+        Placeholder::Location => Some(match (&entry.file, entry.line) {
+            (Some(file), Some(line)) => format!("{}:{}", file, line),
+            _ => entry.target.clone(),
+        }),
+        Placeholder::Message => Some(escape(entry.message.clone(), options)),
+        Placeholder::Kv => pairs(&entry.fields).map(|kv| escape(kv, options)),
+        Placeholder::Context => pairs(&entry.context).map(|kv| escape(kv, options)),
+    }
+}
+
+/// Escape control characters of text coming from the record if enabled
+fn escape(text: String, options: &Options) -> String {
+    if options.escape {
+        format::escape_control(&text).into_owned()
+    } else {
+        text
+    }
+}
+
+/// Render fields as space separated `key=value` pairs, `None` if there are none
+fn pairs(fields: &[(String, serde_json::Value)]) -> Option<String> {
+    if fields.is_empty() {
+        return None;
+    }
+    let mut out = String::new();
+    format::write_fields(&mut out, fields);
+    Some(out.split_off(1))
+}
+
+#[cfg(feature = "color")]
+fn colorize_level(level: String, entry: &Entry, options: &Options) -> String {
+    if options.style != Style::Ansi {
+        return level;
+    }
+    match entry.level {
+        Level::Error => level.red(),
+        Level::Warn => level.yellow(),
+        Level::Info => level.cyan(),
+        Level::Debug => level.purple(),
+        _ => level.normal(),
+    }
+    .to_string()
+}
+
+#[cfg(not(feature = "color"))]
+fn colorize_level(level: String, _entry: &Entry, _options: &Options) -> String {
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::Style;
+    use log::{Level, Record};
+
+    fn render(template: &str, entry: &Entry) -> String {
+        let options = Options {
+            style: Style::Plain,
+            ..Options::default()
+        };
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(entry, &options)
+    }
+
+    #[test]
+    fn placeholders_are_padded_and_optional() {
+        let kvs: &[(&str, log::kv::Value)] = &[("user", "ada".into())];
+        let record = Record::builder()
+            .args(format_args!("login"))
+            .level(Level::Info)
+            .target("app")
+            .module_path(Some("app::auth"))
+            .file(Some("src/auth.rs"))
+            .line(Some(7))
+            .key_values(&kvs)
+            .build();
+        let entry = Entry::new(&record, None, Vec::new(), true);
+        assert_eq!(
+            render("{level:5}|{level:>6}|{level:^8}| {msg}", &entry),
+            "INFO |  INFO|  INFO  | login"
+        );
+        assert_eq!(
+            render(
+                "{module}{?@{file}:{line}} {msg}{? {kv}}{? ctx: {context}}",
+                &entry
+            ),
+            "app::auth@src/auth.rs:7 login user=ada"
+        );
+        assert_eq!(render("{{{level}}}", &entry), "{INFO}");
+        let entry = Entry::new(&record, None, Vec::new(), false);
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, &entry),
+            "[INFO app] login user=ada"
+        );
+        let entry = Entry::new(&record, Some(1700000000123), Vec::new(), false);
+        assert_eq!(
+            render("{time:epoch} {time} {level} {msg}", &entry),
+            "1700000000123 2023-11-14T22:13:20.123Z INFO login"
+        );
+        assert_eq!(render("{time:>15}|", &entry).len(), 25);
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let kvs: &[(&str, log::kv::Value)] = &[("user", "ada\u{1b}[2J".into())];
+        let record = Record::builder()
+            .args(format_args!(
+                "ok\r\n[ERROR app] forged\tline\u{9b}\u{2028}\u{202e}txt.exe\u{2066}"
+            ))
+            .level(Level::Info)
+            .target("app")
+            .key_values(&kvs)
+            .build();
+        let entry = Entry::new(&record, None, Vec::new(), false);
+        let template: Template = "[{level} {target}] {msg} {kv}".parse().unwrap();
+        let options = Options {
+            style: Style::Plain,
+            escape: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            template.render(&entry, &options),
+            "[INFO app] ok\\r\\n[ERROR app] forged\tline\\u{9b}\\u{2028}\\u{202e}txt.exe\\u{2066} \
+             user=ada\\x1b[2J"
+        );
+        let options = Options {
+            escape: false,
+            ..options
+        };
+        assert!(template.render(&entry, &options).contains("ok\r\n[ERROR"));
+    }
+
+    #[test]
+    fn css_directives_style_the_prompt() {
+        let record = Record::builder()
+            .args(format_args!("100% done"))
+            .level(Level::Warn)
+            .target("app")
+            .build();
+        let entry = Entry::new(&record, None, Vec::new(), false);
+        let template: Template = "[{level} {target}] {msg} {level}".parse().unwrap();
+        let options = Options {
+            style: Style::Css,
+            ..Options::default()
+        };
+        let (text, css) = template.render_css(&entry, &options);
+        assert_eq!(text, "%c[%cWARN%c app] %c100%% done %cWARN%c");
+        assert_eq!(
+            css,
+            [
+                "font-weight: bold",
+                "color: darkorange; font-weight: bold",
+                "font-weight: bold",
+                "",
+                "color: darkorange",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let error = |template: &str| template.parse::<Template>().unwrap_err().to_string();
+        assert_eq!(error("{lvl}"), "unknown placeholder `lvl` at position 1");
+        assert_eq!(error("{level:wide}"), "invalid format `wide` at position 1");
+        assert_eq!(error("[{level"), "unclosed placeholder at position 1");
+        assert_eq!(
+            error("{?{level}"),
+            "unclosed optional section at position 2"
+        );
+        assert_eq!(error("level}"), "unmatched `}` at position 5");
+    }
+}
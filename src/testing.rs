@@ -0,0 +1,191 @@
+//! Capturing of emitted records for assertions in tests.
+//!
+//! A process can only install one logger, so [`capture`] installs a logger on the first call and
+//! every [`Capture`] records what the installed logger emits on the current thread. Tests running
+//! in parallel therefore only see their own records.
+//!
+//! ```rust
+//! use worker_logger::testing;
+//!
+//! let logs = testing::capture();
+//! log::warn!(user_id = 42; "quota exceeded");
+//! let entry = logs.assert_logged(log::Level::Warn, "quota");
+//! assert_eq!(entry.field("user_id"), Some(&42.into()));
+//! ```
+
+use log::Level;
+use serde_json::Value;
+
+use crate::sink::Entry;
+use crate::{Logger, INSTALLED};
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+type Records = Rc<RefCell<Vec<Entry>>>;
+
+thread_local! {
+    static CAPTURES: RefCell<Vec<Records>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Start capturing the records emitted on the current thread until the returned [`Capture`] is
+/// dropped
+///
+/// A logger accepting every level is installed if none is installed yet. If one was installed by
+/// this crate, e.g. with [`init_once`](crate::init_once), it is kept along with its filter. Records
+/// sent to a logger from another crate are not captured.
+pub fn capture() -> Capture {
+    if INSTALLED.get().is_none() {
+        // Another thread may win the race, its logger captures just the same
+        let _ = Logger::new("trace").try_set_logger();
+    }
+    let records = Records::default();
+    CAPTURES.with(|captures| captures.borrow_mut().push(records.clone()));
+    Capture { records }
+}
+
+/// Hand an emitted entry to the captures active on the current thread
+pub(crate) fn record(entry: &Entry) {
+    CAPTURES.with(|captures| {
+        for records in captures.borrow().iter() {
+            records.borrow_mut().push(entry.clone());
+        }
+    });
+}
+
+/// The records emitted on the current thread since it was created by [`capture`]
+///
+/// Records logged from other threads, e.g. by a multi-threaded async runtime, are not captured.
+#[must_use = "records are only captured until the capture is dropped"]
+#[derive(Debug)]
+pub struct Capture {
+    records: Records,
+}
+
+impl Capture {
+    /// All captured entries, in the order they were logged
+    pub fn entries(&self) -> Vec<Entry> {
+        self.records.borrow().clone()
+    }
+
+    /// Discard the captured entries
+    pub fn clear(&self) {
+        self.records.borrow_mut().clear();
+    }
+
+    /// Find the first entry of a level whose message contains a substring
+    pub fn find(&self, level: Level, message: &str) -> Option<Entry> {
+        self.records
+            .borrow()
+            .iter()
+            .find(|entry| entry.level == level && entry.message.contains(message))
+            .cloned()
+    }
+
+    /// Whether an entry of a level whose message contains a substring was captured
+    pub fn contains(&self, level: Level, message: &str) -> bool {
+        self.find(level, message).is_some()
+    }
+
+    /// Assert that an entry of a level whose message contains a substring was captured, returning
+    /// it for further assertions
+    #[track_caller]
+    pub fn assert_logged(&self, level: Level, message: &str) -> Entry {
+        match self.find(level, message) {
+            Some(entry) => entry,
+            None => panic!(
+                "no {} record containing {:?} was logged, captured:\n{}",
+                level,
+                message,
+                self.describe()
+            ),
+        }
+    }
+
+    /// Assert that an entry of a level whose message contains a substring was captured with the
+    /// given key-values, either attached to the record or taken from the request context
+    #[track_caller]
+    pub fn assert_logged_with(&self, level: Level, message: &str, fields: &[(&str, Value)]) {
+        let matches = |entry: &Entry| {
+            fields.iter().all(|(key, value)| {
+                entry.field(key).or_else(|| entry.context_field(key)) == Some(value)
+            })
+        };
+        let found =
+            self.records.borrow().iter().any(|entry| {
+                entry.level == level && entry.message.contains(message) && matches(entry)
+            });
+        if !found {
+            panic!(
+                "no {} record containing {:?} with {:?} was logged, captured:\n{}",
+                level,
+                message,
+                fields,
+                self.describe()
+            );
+        }
+    }
+
+    /// Assert that no entry of a level whose message contains a substring was captured
+    #[track_caller]
+    pub fn assert_not_logged(&self, level: Level, message: &str) {
+        if let Some(entry) = self.find(level, message) {
+            panic!("unexpected {} record logged: {:?}", level, entry.message);
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut out = String::new();
+        for entry in self.records.borrow().iter() {
+            let _ = writeln!(out, "  {}", entry.to_json());
+        }
+        if out.is_empty() {
+            out.push_str("  (nothing)\n");
+        }
+        out
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURES.with(|captures| {
+            captures
+                .borrow_mut()
+                .retain(|records| !Rc::ptr_eq(records, &self.records));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestContext;
+
+    #[test]
+    fn captures_are_per_thread_and_scoped() {
+        let logs = capture();
+        let _guard = RequestContext::new().with_field("request_id", "r1").enter();
+        log::warn!(target: "app", user_id = 42; "quota exceeded");
+        std::thread::spawn(|| log::error!("elsewhere"))
+            .join()
+            .unwrap();
+        {
+            let nested = capture();
+            log::error!("nested failure");
+            nested.assert_logged(Level::Error, "failure");
+        }
+        let entry = logs.assert_logged(Level::Warn, "quota");
+        assert_eq!(entry.target, "app");
+        assert_eq!(entry.field("user_id"), Some(&42.into()));
+        logs.assert_logged_with(
+            Level::Warn,
+            "quota",
+            &[("user_id", 42.into()), ("request_id", "r1".into())],
+        );
+        logs.assert_not_logged(Level::Error, "elsewhere");
+        assert_eq!(logs.entries().len(), 2);
+        logs.clear();
+        assert!(!logs.contains(Level::Error, "nested"));
+    }
+}